@@ -0,0 +1,53 @@
+use std::fmt;
+
+/// An iterator applied to a function to derive another, e.g. the `/` in `+/`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Adverb {
+    Each,
+    Over,
+    Scan,
+    Prior,
+    EachRight,
+    EachLeft,
+}
+
+impl Adverb {
+    /// The adverb for the type of a derived function, from 106 to 111.
+    pub fn from_code(code: i8) -> Option<Adverb> {
+        let adverb = match code {
+            106 => Adverb::Each,
+            107 => Adverb::Over,
+            108 => Adverb::Scan,
+            109 => Adverb::Prior,
+            110 => Adverb::EachRight,
+            111 => Adverb::EachLeft,
+            _ => return None,
+        };
+        Some(adverb)
+    }
+
+    pub fn code(self) -> i8 {
+        match self {
+            Adverb::Each      => 106,
+            Adverb::Over      => 107,
+            Adverb::Scan      => 108,
+            Adverb::Prior     => 109,
+            Adverb::EachRight => 110,
+            Adverb::EachLeft  => 111,
+        }
+    }
+}
+
+impl fmt::Display for Adverb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let glyph = match self {
+            Adverb::Each      => "'",
+            Adverb::Over      => "/",
+            Adverb::Scan      => "\\",
+            Adverb::Prior     => "':",
+            Adverb::EachRight => "/:",
+            Adverb::EachLeft  => "\\:",
+        };
+        write!(f, "{}", glyph)
+    }
+}
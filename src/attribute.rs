@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// The attribute of a simple list, e.g. `` `s# `` for a list that q knows is
+/// sorted. q trusts the attribute it is sent, so only set one that holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Attribute {
+    #[default]
+    None,
+    Sorted,
+    Unique,
+    Parted,
+    Grouped,
+}
+
+impl Attribute {
+    /// The attribute for its byte on the wire.
+    pub fn from_code(code: u8) -> Option<Attribute> {
+        let attribute = match code {
+            0 => Attribute::None,
+            1 => Attribute::Sorted,
+            2 => Attribute::Unique,
+            3 => Attribute::Parted,
+            4 => Attribute::Grouped,
+            _ => return None,
+        };
+        Some(attribute)
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Attribute::None    => 0,
+            Attribute::Sorted  => 1,
+            Attribute::Unique  => 2,
+            Attribute::Parted  => 3,
+            Attribute::Grouped => 4,
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Attribute::None    => Ok(()),
+            Attribute::Sorted  => write!(f, "`s#"),
+            Attribute::Unique  => write!(f, "`u#"),
+            Attribute::Parted  => write!(f, "`p#"),
+            Attribute::Grouped => write!(f, "`g#"),
+        }
+    }
+}
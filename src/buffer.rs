@@ -0,0 +1,58 @@
+use std::sync::{Arc, Mutex};
+
+/// A shared stock of byte buffers for message bodies.
+///
+/// Clones share the same buffers, so one pool can serve several connections.
+/// `Kdb::read_lazy` takes its buffers from the pool when one is set, and
+/// they can be handed back with `put` once the message is finished with, e.g.
+/// from `Lazy::into_body`.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    /// A pool that holds on to at most `max_buffers` idle buffers.
+    pub fn new(max_buffers: usize) -> BufferPool {
+        BufferPool { buffers: Arc::new(Mutex::new(Vec::with_capacity(max_buffers))), max_buffers }
+    }
+
+    /// An idle buffer, or a new empty one if there are none.
+    pub fn get(&self) -> Vec<u8> {
+        self.buffers.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Give a buffer back to the pool. It is dropped if the pool is full.
+    pub fn put(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        };
+    }
+
+    /// The number of idle buffers.
+    pub fn idle(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_buffers() {
+        let pool = BufferPool::new(1);
+        let mut buf = pool.get();
+        buf.extend_from_slice(&[1, 2, 3]);
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+        pool.put(Vec::with_capacity(8));
+        assert_eq!(pool.idle(), 1);
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+    }
+}
@@ -0,0 +1,426 @@
+use std::fmt;
+use std::io::{ErrorKind, Error};
+use crate::KObj;
+use super::attribute::Attribute;
+use super::ktype::KType;
+use super::kvector::KVector;
+use super::symbol::SymbolInterner;
+use super::text::TextMode;
+use super::view::{KView, VectorView, EnumView, Column, Symbols, FixedWidth};
+
+/// The error for a value of a type rsq can't read, such as a foreign (112).
+/// Nothing says how long such a value is, so the rest of its message is lost.
+#[derive(Debug)]
+pub(crate) struct Unsupported(pub i8);
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "type {} unsupported by rsq", self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+// fails with ErrorKind::Unsupported, wherever in a message the type is
+fn unsupported(code: i8) -> Error {
+    Error::new(ErrorKind::Unsupported, Unsupported(code))
+}
+
+pub(crate) fn is_unsupported(e: &Error) -> bool {
+    e.get_ref().is_some_and(|e| e.is::<Unsupported>())
+}
+
+// every type KObj::new doesn't know becomes an error prototype
+pub(crate) fn is_unsupported_type(code: i8) -> bool {
+    code == 112 || (code != -128 && matches!(KObj::new(code), KObj::Error(_)))
+}
+
+/// Parses a message body that has already been read into memory, borrowing
+/// from it wherever possible.
+///
+/// Every read is bounds checked, so a malformed message fails with
+/// `ErrorKind::InvalidData` rather than reading into the next message. A
+/// message holding a type rsq can't read fails with `ErrorKind::Unsupported`.
+pub(crate) struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+pub(crate) fn invalid<E>(e: E) -> Error
+where E: Into<Box<dyn std::error::Error + Send + Sync>> {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Decoder<'a> {
+        Decoder { data, pos: 0 }
+    }
+
+    /// Decode a whole message body: a type byte followed by its value.
+    pub fn decode(&mut self) -> Result<KView<'a>, Error> {
+        let msg_type = self.read_type()?;
+        self.read_data(msg_type)
+    }
+
+    /// Decode a whole message body into owned values, reading symbols and
+    /// chars as `text` says rather than requiring them to be UTF-8.
+    pub fn decode_owned(&mut self, text: TextMode, mut interner: Option<&mut SymbolInterner>) -> Result<KObj, Error> {
+        let msg_type = self.read_type()?;
+        self.read_owned(msg_type, text, &mut interner)
+    }
+
+    /// Step over the next value without decoding it.
+    pub fn skip(&mut self) -> Result<(), Error> {
+        let msg_type = self.read_type()?;
+        if is_unsupported_type(msg_type) {
+            return Err(unsupported(msg_type))
+        };
+        match KObj::new(msg_type) {
+            KObj::Vector(_, v) => {
+                self.take(1)?;
+                let len = self.read_len()?;
+                match v.width() {
+                    Some(width) => {
+                        let size = len.checked_mul(width).ok_or_else(|| invalid("vector is too long"))?;
+                        self.take(size)?;
+                    },
+                    None => for _ in 0..len {
+                        self.extract_sym()?;
+                    },
+                };
+            },
+            KObj::GenericList(_) => {
+                self.take(1)?;
+                for _ in 0..self.read_len()? {
+                    self.skip()?;
+                };
+            },
+            KObj::Dict(_,_) => {
+                self.skip()?;
+                self.skip()?;
+            },
+            KObj::Table(_,_) => {
+                self.take(1)?;
+                self.skip()?;
+            },
+            // atoms, lambdas and errors are small enough to just read
+            _ => { self.read_data(msg_type)?; },
+        };
+        Ok(())
+    }
+
+    /// How far into the data the decoder has got.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("message is shorter than its contents"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn read_type(&mut self) -> Result<i8, Error> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    pub fn read_len(&mut self) -> Result<usize, Error> {
+        let len = self.take(4)?;
+        Ok(u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+    }
+
+    fn extract_sym(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.data[self.pos..].iter().position(|b| *b == 0)
+            .ok_or_else(|| invalid("symbol is missing its terminator"))?;
+        let sym = self.take(len)?;
+        self.pos += 1;
+        Ok(sym)
+    }
+
+    fn read_atom(&mut self, ktype: KType) -> Result<KView<'a>, Error> {
+        let data = match ktype {
+            KType::Boolean(_)   => self.take(1)?,
+            KType::Guid(_)      => self.take(16)?,
+            KType::Byte(_)      => self.take(1)?,
+            KType::Short(_)     => self.take(2)?,
+            KType::Int(_)       => self.take(4)?,
+            KType::Long(_)      => self.take(8)?,
+            KType::Real(_)      => self.take(4)?,
+            KType::Float(_)     => self.take(8)?,
+            KType::Char(_)      => return Ok(KView::Atom(KType::Char(TextMode::Strict.char(self.take(1)?[0])?))),
+            KType::Symbol(_)    => return Ok(KView::Symbol(utf8(self.extract_sym()?)?)),
+            KType::Timestamp(_) => self.take(8)?,
+            KType::Month(_)     => self.take(4)?,
+            KType::Date(_)      => self.take(4)?,
+            KType::Datetime(_)  => self.take(8)?,
+            KType::Timespan(_)  => self.take(8)?,
+            KType::Minute(_)    => self.take(4)?,
+            KType::Second(_)    => self.take(4)?,
+            KType::Time(_)      => self.take(4)?,
+            KType::Unary(_)     => self.take(1)?,
+            KType::Operator(_)  => self.take(1)?,
+            KType::Iterator(_)  => self.take(1)?,
+        };
+        Ok(KView::Atom(ktype.deserialize(data)))
+    }
+
+    fn read_symbols(&mut self, len: usize) -> Result<Symbols<'a>, Error> {
+        let start = self.pos;
+        for _ in 0..len {
+            self.extract_sym()?;
+        };
+        // null terminators are valid utf8, so the symbols can be checked in one go
+        Ok(Symbols::new(utf8(&self.data[start..self.pos])?, len))
+    }
+
+    fn read_column<T: FixedWidth>(&mut self, len: usize) -> Result<Column<'a, T>, Error> {
+        let size = len.checked_mul(T::WIDTH).ok_or_else(|| invalid("vector is too long"))?;
+        Ok(Column::new(self.take(size)?))
+    }
+
+    fn read_vector(&mut self, attribute: Attribute, vector: KVector, len: usize) -> Result<KView<'a>, Error> {
+        let vector = match vector {
+            KVector::Boolean(_)   => VectorView::Boolean(self.read_column(len)?),
+            KVector::Guid(_)      => VectorView::Guid(self.read_column(len)?),
+            KVector::Byte(_)      => VectorView::Byte(self.take(len)?),
+            KVector::Short(_)     => VectorView::Short(self.read_column(len)?),
+            KVector::Int(_)       => VectorView::Int(self.read_column(len)?),
+            KVector::Long(_)      => VectorView::Long(self.read_column(len)?),
+            KVector::Real(_)      => VectorView::Real(self.read_column(len)?),
+            KVector::Float(_)     => VectorView::Float(self.read_column(len)?),
+            KVector::Char(_)      => VectorView::Char(self.take(len)?),
+            KVector::Symbol(_)    => VectorView::Symbol(self.read_symbols(len)?),
+            KVector::Timestamp(_) => VectorView::Timestamp(self.read_column(len)?),
+            KVector::Month(_)     => VectorView::Month(self.read_column(len)?),
+            KVector::Date(_)      => VectorView::Date(self.read_column(len)?),
+            KVector::Datetime(_)  => VectorView::Datetime(self.read_column(len)?),
+            KVector::Timespan(_)  => VectorView::Timespan(self.read_column(len)?),
+            KVector::Minute(_)    => VectorView::Minute(self.read_column(len)?),
+            KVector::Second(_)    => VectorView::Second(self.read_column(len)?),
+            KVector::Time(_)      => VectorView::Time(self.read_column(len)?),
+        };
+        Ok(KView::Vector(attribute, vector))
+    }
+
+    fn read_generic_list(&mut self, len: usize) -> Result<KView<'a>, Error> {
+        let mut list = Vec::with_capacity(len.min(self.data.len()));
+        for _ in 0..len {
+            let msg_code = self.read_type()?;
+            list.push(self.read_data(msg_code)?);
+        };
+        Ok(KView::GenericList(list))
+    }
+
+    fn read_list(&mut self, list: KObj) -> Result<KView<'a>, Error> {
+        let attribute = self.read_attribute()?;
+        let len = self.read_len()?;
+        match list {
+            KObj::Vector(_, v) => self.read_vector(attribute, v, len),
+            _ => self.read_generic_list(len),
+        }
+    }
+
+    fn read_attribute(&mut self) -> Result<Attribute, Error> {
+        let code = self.take(1)?[0];
+        Attribute::from_code(code).ok_or_else(|| invalid(format!("unknown attribute {}", code)))
+    }
+
+    fn read_list_of_any(&mut self) -> Result<KView<'a>, Error> {
+        let list_type = self.read_type()?;
+        match self.read_data(list_type)? {
+            list @ (KView::Vector(_, _) | KView::GenericList(_) | KView::Table(_, _)) => Ok(list),
+            KView::Enum(a, e) if e.code > 0 => Ok(KView::Enum(a, e)),
+            _ => Err(invalid("keys and values of a dictionary must be lists or tables")),
+        }
+    }
+
+    fn read_dict(&mut self) -> Result<KView<'a>, Error> {
+        let keys = self.read_list_of_any()?;
+        let vals = self.read_list_of_any()?;
+        Ok(KView::Dict(Box::new(keys), Box::new(vals)))
+    }
+
+    fn read_table(&mut self) -> Result<KView<'a>, Error> {
+        // attribute, then the type byte of the column dictionary
+        self.take(2)?;
+        let names = match self.read_list_of_any()? {
+            KView::Vector(_, VectorView::Symbol(names)) => names,
+            _ => return Err(invalid("table column names must be symbols")),
+        };
+        let columns = match self.read_list_of_any()? {
+            KView::GenericList(columns) => columns,
+            _ => return Err(invalid("table columns must be a general list")),
+        };
+        Ok(KView::Table(names, columns))
+    }
+
+    fn read_enum(&mut self, code: i8) -> Result<KView<'a>, Error> {
+        // an atom is just the domain and one index
+        let attribute = if code > 0 { self.read_attribute()? } else { Attribute::None };
+        let domain = utf8(self.extract_sym()?)?;
+        let len = if code > 0 { self.read_len()? } else { 1 };
+        let indices = self.read_column(len)?;
+        Ok(KView::Enum(attribute, EnumView { code, domain, indices }))
+    }
+
+    // the context, then the body as a char vector
+    fn read_lambda(&mut self) -> Result<KView<'a>, Error> {
+        let context = utf8(self.extract_sym()?)?;
+        self.take(2)?;
+        let len = self.read_len()?;
+        Ok(KView::Lambda { context, body: utf8(self.take(len)?)? })
+    }
+
+    fn read_error(&mut self) -> Result<KView<'a>, Error> {
+        Ok(KView::Error(utf8(self.extract_sym()?)?))
+    }
+
+    // the function and arguments of a projection, or the functions of a composition
+    fn read_functions(&mut self) -> Result<Vec<KView<'a>>, Error> {
+        let len = self.read_len()?;
+        let mut items = Vec::with_capacity(len.min(self.data.len()));
+        for _ in 0..len {
+            items.push(self.read_function()?);
+        };
+        Ok(items)
+    }
+
+    fn read_function(&mut self) -> Result<KView<'a>, Error> {
+        let msg_type = self.read_type()?;
+        self.read_data(msg_type)
+    }
+
+    fn read_owned_functions(&mut self, text: TextMode, interner: &mut Option<&mut SymbolInterner>) -> Result<Vec<KObj>, Error> {
+        let len = self.read_len()?;
+        let mut items = Vec::with_capacity(len.min(self.data.len()));
+        for _ in 0..len {
+            items.push(self.read_owned_function(text, interner)?);
+        };
+        Ok(items)
+    }
+
+    fn read_owned_function(&mut self, text: TextMode, interner: &mut Option<&mut SymbolInterner>) -> Result<KObj, Error> {
+        let msg_type = self.read_type()?;
+        self.read_owned(msg_type, text, interner)
+    }
+
+    // everything that holds text is read here, and the rest is read as a view
+    fn read_owned(&mut self, msg_type: i8, text: TextMode, interner: &mut Option<&mut SymbolInterner>) -> Result<KObj, Error> {
+        if is_unsupported_type(msg_type) {
+            return Err(unsupported(msg_type))
+        };
+        let kobj = match KObj::new(msg_type) {
+            KObj::Atom(KType::Symbol(_)) => KObj::Atom(KType::Symbol(text.symbol(self.extract_sym()?, interner.as_deref_mut())?)),
+            KObj::Atom(KType::Char(_)) => KObj::Atom(KType::Char(text.char(self.take(1)?[0])?)),
+            KObj::Vector(_, KVector::Symbol(_)) => {
+                let attribute = self.read_attribute()?;
+                let len = self.read_len()?;
+                let mut symbols = Vec::with_capacity(len.min(self.data.len()));
+                for _ in 0..len {
+                    symbols.push(text.symbol(self.extract_sym()?, interner.as_deref_mut())?);
+                };
+                KObj::Vector(attribute, KVector::Symbol(symbols))
+            },
+            KObj::GenericList(_) => {
+                self.take(1)?;
+                let len = self.read_len()?;
+                let mut list = Vec::with_capacity(len.min(self.data.len()));
+                for _ in 0..len {
+                    let msg_code = self.read_type()?;
+                    list.push(self.read_owned(msg_code, text, interner)?);
+                };
+                KObj::GenericList(list)
+            },
+            KObj::Dict(_,_) => {
+                let keys = self.read_owned_list(text, interner)?;
+                let vals = self.read_owned_list(text, interner)?;
+                KObj::dict(keys, vals)
+            },
+            KObj::Table(_,_) => {
+                self.take(2)?;
+                let names = match self.read_owned_list(text, interner)? {
+                    KObj::Vector(_, KVector::Symbol(names)) => names,
+                    _ => return Err(invalid("table column names must be symbols")),
+                };
+                let columns = match self.read_owned_list(text, interner)? {
+                    KObj::GenericList(columns) => columns,
+                    _ => return Err(invalid("table columns must be a general list")),
+                };
+                KObj::Table(names.into_iter().map(|n| KObj::Atom(KType::Symbol(n))).collect(), columns)
+            },
+            KObj::Lambda { .. } => {
+                let context = text.symbol(self.extract_sym()?, interner.as_deref_mut())?;
+                self.take(2)?;
+                let len = self.read_len()?;
+                KObj::Lambda { context, body: text.text(self.take(len)?)?.into_owned() }
+            },
+            KObj::Error(_) => KObj::Error(text.text(self.extract_sym()?)?.into_owned()),
+            KObj::Projection(_) => KObj::Projection(self.read_owned_functions(text, interner)?),
+            KObj::Composition(_) => KObj::Composition(self.read_owned_functions(text, interner)?),
+            KObj::Adverb(a, _) => KObj::Adverb(a, Box::new(self.read_owned_function(text, interner)?)),
+            _ => self.read_data(msg_type)?.into_kobj(),
+        };
+        Ok(kobj)
+    }
+
+    fn read_owned_list(&mut self, text: TextMode, interner: &mut Option<&mut SymbolInterner>) -> Result<KObj, Error> {
+        let list_type = self.read_type()?;
+        match self.read_owned(list_type, text, interner)? {
+            list @ (KObj::Vector(_, _) | KObj::GenericList(_) | KObj::Table(_, _)) => Ok(list),
+            KObj::Enum(a, e) if !e.is_atom() => Ok(KObj::Enum(a, e)),
+            _ => Err(invalid("keys and values of a dictionary must be lists or tables")),
+        }
+    }
+
+    pub fn read_data(&mut self, msg_type: i8) -> Result<KView<'a>, Error> {
+        if is_unsupported_type(msg_type) {
+            return Err(unsupported(msg_type))
+        };
+        match KObj::new(msg_type) {
+            KObj::Atom(k) => self.read_atom(k),
+            list @ (KObj::List(_, _) | KObj::Vector(_, _) | KObj::GenericList(_)) => self.read_list(list),
+            KObj::Enum(_, _) => self.read_enum(msg_type),
+            KObj::Dict(_,_) => self.read_dict(),
+            KObj::Table(_,_) => self.read_table(),
+            KObj::Lambda { .. } => self.read_lambda(),
+            KObj::Error(_) => self.read_error(),
+            KObj::Projection(_) => Ok(KView::Projection(self.read_functions()?)),
+            KObj::Composition(_) => Ok(KView::Composition(self.read_functions()?)),
+            KObj::Adverb(a, _) => Ok(KView::Adverb(a, Box::new(self.read_function()?))),
+            KObj::Opaque { type_code, .. } => Err(unsupported(type_code)),
+        }
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(bytes).map_err(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Enumeration;
+
+    // `sym$`b`a`b, where sym:`a`b
+    const ENUM: [u8; 34] = [20, 0, 115, 121, 109, 0, 3, 0, 0, 0,
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn read_enum() {
+        let data = KView::decode(&ENUM).unwrap().into_kobj();
+        assert_eq!(data, KObj::Enum(Attribute::None, Enumeration { code: 20, domain: "sym".into(), indices: vec![1, 0, 1] }));
+        assert_eq!(data.serialize(), &ENUM[1..]);
+        assert_eq!(format!("{}", data), "`sym!(1j;0j;1j)");
+        let mut decoder = Decoder::new(&ENUM);
+        decoder.skip().unwrap();
+        assert_eq!(decoder.position(), ENUM.len());
+        // `sym$`b
+        let atom = KView::decode(&[236, 115, 121, 109, 0, 1, 0, 0, 0, 0, 0, 0, 0]).unwrap().into_kobj();
+        assert_eq!(atom, KObj::Enum(Attribute::None, Enumeration { code: -20, domain: "sym".into(), indices: vec![1] }));
+        assert_eq!(atom.encoded_len(), 13);
+        assert_eq!(KObj::new(-128), KObj::Error(String::new()));
+    }
+}
@@ -0,0 +1,19 @@
+/// Connection details for a single kdb+ process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub pass: String,
+}
+
+impl Endpoint {
+    pub fn new(host: &str, port: u16, user: &str, pass: &str) -> Endpoint {
+        Endpoint {
+            host: host.to_string(),
+            port,
+            user: user.to_string(),
+            pass: pass.to_string(),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Error, ErrorKind, Write};
+use byteorder::{LittleEndian, WriteBytesExt};
+use crate::KObj;
+use super::attribute::Attribute;
+use super::ktype::KType;
+use super::kvector::KVector;
+use super::null;
+use super::symbol::Symbol;
+
+/// A value of an enumerated symbol type, e.g. `` `sym$`a`b ``, held as the
+/// name of its domain and indices into it rather than as the symbols
+/// themselves. Use `resolve`, or `Kdb::resolve_enum`, to get the symbols.
+///
+/// `code` is the kdb+ type, from 20 to 76 for a list, or negated for an
+/// atom, which has exactly one index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enumeration {
+    pub code: i8,
+    pub domain: Symbol,
+    pub indices: Vec<i64>,
+}
+
+impl Enumeration {
+    pub fn is_atom(&self) -> bool {
+        self.code < 0
+    }
+
+    /// Look each index up in `symbols`, the domain's values, returning a
+    /// symbol atom or vector. Null indices give the null symbol.
+    pub fn resolve(&self, symbols: &[Symbol]) -> Result<KObj, Error> {
+        let mut resolved = self.indices.iter().map(|i| match *i {
+            null::LONG => Ok(Symbol::from(null::SYMBOL)),
+            i => usize::try_from(i).ok().and_then(|i| symbols.get(i)).cloned()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("index {} is not in `{}", i, self.domain))),
+        }).collect::<Result<Vec<_>, Error>>()?;
+        if !self.is_atom() {
+            return Ok(KObj::from(KVector::Symbol(resolved)))
+        };
+        match resolved.pop() {
+            Some(sym) if resolved.is_empty() => Ok(KObj::Atom(KType::Symbol(sym))),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "an enumerated atom has exactly one index")),
+        }
+    }
+
+    pub(crate) fn serialize_with<W: Write>(&self, attribute: Attribute, w: &mut W) -> io::Result<()> {
+        if !self.is_atom() {
+            w.write_u8(attribute.code())?;
+        };
+        w.write_all(self.domain.as_bytes())?;
+        w.write_u8(0)?;
+        if self.is_atom() {
+            return w.write_i64::<LittleEndian>(self.indices.first().copied().unwrap_or(null::LONG))
+        };
+        w.write_i32::<LittleEndian>(self.indices.len() as i32)?;
+        self.indices.iter().try_for_each(|i| w.write_i64::<LittleEndian>(*i))
+    }
+
+    pub(crate) fn serialized_len(&self) -> usize {
+        let domain = self.domain.as_bytes().len() + 1;
+        if self.is_atom() { domain + 8 } else { 1 + domain + 4 + 8 * self.indices.len() }
+    }
+}
+
+impl fmt::Display for Enumeration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // `sym!0 1 is how q builds an enumeration from its indices
+        match self.indices.first() {
+            Some(i) if self.is_atom() => write!(f, "`{}!{}", self.domain, KType::Long(*i)),
+            _ => write!(f, "`{}!{}", self.domain, KVector::Long(self.indices.clone())),
+        }
+    }
+}
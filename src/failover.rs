@@ -0,0 +1,375 @@
+use std::net::TcpStream;
+use std::io::{ErrorKind, Error};
+use crate::KObj;
+use super::endpoint::Endpoint;
+use super::kdb::Kdb;
+
+type TcpKdb = Kdb<TcpStream, TcpStream>;
+type Listener = Box<dyn FnMut(&FailoverEvent) + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailoverMode {
+    /// Stay on the first working endpoint until its connection is lost.
+    Failover,
+    /// Move to the next endpoint before every sync query, for spreading
+    /// read-only load. Endpoints that can't be reached are skipped.
+    RoundRobin,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FailoverEvent {
+    Connected(Endpoint),
+    FailedOver { from: Endpoint, to: Endpoint },
+    AllEndpointsDown,
+}
+
+/// A connection to one of an ordered list of endpoints, e.g. a primary and
+/// secondary RDB.
+///
+/// A connection that is found to be lost before a send is dropped and the
+/// send goes to the next endpoint in the list instead. Once any of a message
+/// has been written it is never sent again, since the query may already have
+/// run: if the connection is lost during the send the error is returned and
+/// the next send goes to the next endpoint. Any other error, such as a reply
+/// that can't be decoded, is returned as it is and keeps the connection.
+///
+/// Async messages still buffered on a lost connection, e.g. under
+/// `FlushPolicy::Manual`, can't be delivered. Failing over from it reports
+/// them with `ErrorKind::BrokenPipe` rather than dropping them quietly.
+pub struct FailoverKdb {
+    endpoints: Vec<Endpoint>,
+    mode: FailoverMode,
+    connections: Vec<Option<TcpKdb>>,
+    current: usize,
+    listener: Option<Listener>,
+}
+
+impl FailoverKdb {
+    pub fn new(endpoints: Vec<Endpoint>) -> FailoverKdb {
+        assert!(!endpoints.is_empty(), "failover needs at least one endpoint");
+        FailoverKdb {
+            connections: endpoints.iter().map(|_| None).collect(),
+            endpoints,
+            mode: FailoverMode::Failover,
+            current: 0,
+            listener: None,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: FailoverMode) -> FailoverKdb {
+        self.mode = mode;
+        self
+    }
+
+    /// Register a callback that is told about every connection event.
+    pub fn on_event<F: FnMut(&FailoverEvent) + Send + 'static>(mut self, listener: F) -> FailoverKdb {
+        self.listener = Some(Box::new(listener));
+        self
+    }
+
+    /// Connect to the first endpoint in the list that accepts a connection.
+    pub fn open(&mut self) -> Result<(), Error> {
+        self.connect_from(0, None)
+    }
+
+    /// The endpoint that the next message will be sent to.
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoints[self.current]
+    }
+
+    pub fn kdb(&mut self) -> Option<&mut TcpKdb> {
+        self.connections[self.current].as_mut()
+    }
+
+    pub fn send_async(&mut self, data: &KObj) -> Result<(), Error> {
+        self.with_connection(|kdb| kdb.send_async(data))
+    }
+
+    pub fn send_sync(&mut self, data: &KObj) -> Result<KObj, Error> {
+        if self.mode == FailoverMode::RoundRobin {
+            let next = (self.current + 1) % self.endpoints.len();
+            self.connect_from(next, None)?;
+        };
+        self.with_connection(|kdb| kdb.send_sync(data))
+    }
+
+    /// Read the next message from the current endpoint.
+    ///
+    /// If the connection is lost the next endpoint is connected to, but the
+    /// read error is still returned: anything the old connection was set up
+    /// for, such as a tickerplant subscription, has to be redone by the caller.
+    /// A message that can't be decoded is skipped and leaves the connection be.
+    pub fn read(&mut self) -> Result<KObj, Error> {
+        let msg = match self.kdb() {
+            Some(kdb) => kdb.read(),
+            None => Err(Error::new(ErrorKind::NotConnected, "no reader available")),
+        };
+        match msg {
+            Err(e) if is_connection_lost(&e) => {
+                self.fail_over()?;
+                Err(e)
+            },
+            msg => msg,
+        }
+    }
+
+    /// Close every open connection, even if closing one of them fails, and
+    /// return the first error.
+    pub fn close(&mut self) -> Result<(), Error> {
+        let mut first_err = None;
+        for mut kdb in self.connections.iter_mut().filter_map(Option::take) {
+            if let Err(e) = kdb.close() {
+                first_err.get_or_insert(e);
+            };
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    fn with_connection<T, F>(&mut self, f: F) -> Result<T, Error>
+    where F: FnOnce(&mut TcpKdb) -> Result<T, Error> {
+        match self.kdb().map(|kdb| kdb.is_connected() && !kdb.has_hung_up()) {
+            Some(true) => {},
+            // nothing has been written yet, so the message can go elsewhere
+            Some(false) => self.fail_over()?,
+            // never opened, or closed since
+            None => self.connect_from(self.current, None)?,
+        };
+        let result = f(self.kdb().unwrap());
+        if let Err(e) = &result {
+            if is_connection_lost(e) {
+                self.fail_over()?;
+            };
+        };
+        result
+    }
+
+    fn fail_over(&mut self) -> Result<(), Error> {
+        let from = self.current;
+        let mut lost = 0;
+        if let Some(mut kdb) = self.connections[from].take() {
+            lost = kdb.buffered_output();
+            kdb.close().ok();
+        };
+        self.connect_from((from + 1) % self.endpoints.len(), Some(from))?;
+        if lost > 0 {
+            let endpoint = &self.endpoints[from];
+            return Err(Error::new(ErrorKind::BrokenPipe, format!("lost {} bytes of buffered messages to {}:{}",
+                lost, endpoint.host, endpoint.port)))
+        };
+        Ok(())
+    }
+
+    // use the first endpoint from `start` that is already open or can be
+    // opened, wrapping around the list at most once
+    fn connect_from(&mut self, start: usize, failed: Option<usize>) -> Result<(), Error> {
+        for i in 0..self.endpoints.len() {
+            let index = (start + i) % self.endpoints.len();
+            let opened = self.connections[index].is_none();
+            if opened {
+                let mut kdb = Kdb::from_endpoint(&self.endpoints[index]);
+                if kdb.open().is_err() {
+                    continue
+                };
+                self.connections[index] = Some(kdb);
+            };
+            self.current = index;
+            // moving on to a connection that's already open is only news
+            // after a failure
+            let event = match failed {
+                Some(from) => FailoverEvent::FailedOver {
+                    from: self.endpoints[from].clone(),
+                    to: self.endpoints[index].clone(),
+                },
+                None if opened => FailoverEvent::Connected(self.endpoints[index].clone()),
+                None => return Ok(()),
+            };
+            self.emit(event);
+            return Ok(())
+        }
+        self.emit(FailoverEvent::AllEndpointsDown);
+        Err(Error::new(ErrorKind::NotConnected, "all endpoints are down"))
+    }
+
+    fn emit(&mut self, event: FailoverEvent) {
+        if let Some(listener) = self.listener.as_mut() {
+            listener(&event);
+        };
+    }
+}
+
+fn is_connection_lost(e: &Error) -> bool {
+    matches!(e.kind(), ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset | ErrorKind::BrokenPipe
+        | ErrorKind::UnexpectedEof | ErrorKind::NotConnected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake_q, FlushPolicy, KType};
+    use std::sync::{Arc, Mutex};
+    use std::io::Write;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn connects_to_first_working_endpoint() {
+        let (dead, live) = (fake_q::dead(), fake_q::spawn());
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        let mut kdb = FailoverKdb::new(vec![dead, live.clone()])
+            .on_event(move |e| seen.lock().unwrap().push(e.clone()));
+        kdb.open().unwrap();
+        assert_eq!(kdb.endpoint(), &live);
+        assert_eq!(*events.lock().unwrap(), vec![FailoverEvent::Connected(live)]);
+    }
+
+    #[test]
+    fn connects_on_first_send() {
+        let (a, b) = (fake_q::spawn(), fake_q::spawn());
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        let mut kdb = FailoverKdb::new(vec![a.clone(), b])
+            .on_event(move |e| seen.lock().unwrap().push(e.clone()));
+        let ping = KObj::Atom(KType::Boolean(true));
+        assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        assert_eq!(kdb.endpoint(), &a);
+        assert_eq!(*events.lock().unwrap(), vec![FailoverEvent::Connected(a)]);
+    }
+
+    #[test]
+    fn closes_every_connection() {
+        let mut kdb = FailoverKdb::new(vec![fake_q::spawn(), fake_q::spawn()]).with_mode(FailoverMode::RoundRobin);
+        kdb.open().unwrap();
+        kdb.send_sync(&KObj::Atom(KType::Boolean(true))).unwrap();
+        assert!(kdb.connections.iter().all(Option::is_some));
+        kdb.close().unwrap();
+        assert!(kdb.connections.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn reports_all_endpoints_down() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        let mut kdb = FailoverKdb::new(vec![fake_q::dead(), fake_q::dead()])
+            .on_event(move |e| seen.lock().unwrap().push(e.clone()));
+        assert_eq!(kdb.open().unwrap_err().kind(), ErrorKind::NotConnected);
+        assert_eq!(*events.lock().unwrap(), vec![FailoverEvent::AllEndpointsDown]);
+    }
+
+    #[test]
+    fn fails_over_on_connection_loss() {
+        let (primary, secondary) = (fake_q::spawn_once(), fake_q::spawn());
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        let mut kdb = FailoverKdb::new(vec![primary.clone(), secondary.clone()])
+            .on_event(move |e| seen.lock().unwrap().push(e.clone()));
+        kdb.open().unwrap();
+        let ping = KObj::Atom(KType::Boolean(true));
+        assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        wait_for_hang_up(&mut kdb);
+        assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        assert_eq!(kdb.endpoint(), &secondary);
+        assert_eq!(*events.lock().unwrap(), vec![
+            FailoverEvent::Connected(primary.clone()),
+            FailoverEvent::FailedOver { from: primary, to: secondary },
+        ]);
+    }
+
+    #[test]
+    fn does_not_resend_a_query_lost_in_flight() {
+        let (primary, sent) = fake_q::spawn_hanging_up();
+        let (secondary, queries) = fake_q::spawn_replying(&[255, 1]);
+        let mut kdb = FailoverKdb::new(vec![primary, secondary.clone()]);
+        kdb.open().unwrap();
+        let ping = KObj::Atom(KType::Boolean(true));
+        assert!(is_connection_lost(&kdb.send_sync(&ping).unwrap_err()));
+        assert_eq!((sent.load(Ordering::SeqCst), queries.load(Ordering::SeqCst)), (1, 0));
+        // the next query goes to the secondary
+        assert_eq!(kdb.endpoint(), &secondary);
+        assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn reports_buffered_messages_lost_with_the_connection() {
+        let (primary, secondary) = (fake_q::spawn_once(), fake_q::spawn());
+        let mut kdb = FailoverKdb::new(vec![primary, secondary.clone()]);
+        kdb.open().unwrap();
+        let ping = KObj::Atom(KType::Boolean(true));
+        assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        wait_for_hang_up(&mut kdb);
+        let primary = kdb.kdb().unwrap();
+        primary.set_flush_policy(FlushPolicy::Manual);
+        primary.send_async(&ping).unwrap();
+        assert_eq!(kdb.send_async(&ping).unwrap_err().kind(), ErrorKind::BrokenPipe);
+        assert_eq!(kdb.endpoint(), &secondary);
+        kdb.send_async(&ping).unwrap();
+    }
+
+    #[test]
+    fn keeps_connection_on_bad_reply() {
+        // a long vector with an unknown attribute
+        let (primary, queries) = fake_q::spawn_replying(&[7, 9, 0, 0, 0, 0]);
+        let mut kdb = FailoverKdb::new(vec![primary.clone(), fake_q::spawn()]);
+        kdb.open().unwrap();
+        let ping = KObj::Atom(KType::Boolean(true));
+        assert_eq!(kdb.send_sync(&ping).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+        assert_eq!(kdb.endpoint(), &primary);
+    }
+
+    #[test]
+    fn read_keeps_connection_on_bad_message() {
+        let (primary, queries) = fake_q::spawn_replying(&[7, 9, 0, 0, 0, 0]);
+        let mut kdb = FailoverKdb::new(vec![primary.clone(), fake_q::spawn()]);
+        kdb.open().unwrap();
+        // 1b sent as a sync message, to make the fake q push its reply
+        let writer = kdb.kdb().unwrap().writer();
+        writer.write_all(&[1, 1, 0, 0, 10, 0, 0, 0, 255, 1]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(kdb.endpoint(), &primary);
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+        assert!(kdb.kdb().is_some());
+    }
+
+    #[test]
+    fn round_robin_rotates_endpoints() {
+        let (a, b) = (fake_q::spawn(), fake_q::spawn());
+        let mut kdb = FailoverKdb::new(vec![a.clone(), b.clone()]).with_mode(FailoverMode::RoundRobin);
+        kdb.open().unwrap();
+        let ping = KObj::Atom(KType::Boolean(true));
+        assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        assert_eq!(kdb.endpoint(), &b);
+        assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        assert_eq!(kdb.endpoint(), &a);
+    }
+
+    #[test]
+    fn round_robin_reports_failing_over_to_an_open_connection() {
+        let (a, b) = (fake_q::spawn(), fake_q::spawn_once());
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        let mut kdb = FailoverKdb::new(vec![a.clone(), b.clone()])
+            .with_mode(FailoverMode::RoundRobin)
+            .on_event(move |e| seen.lock().unwrap().push(e.clone()));
+        kdb.open().unwrap();
+        let ping = KObj::Atom(KType::Boolean(true));
+        // b answers once, then a is rotated to, then b has hung up
+        for _ in 0..3 {
+            assert_eq!(kdb.send_sync(&ping).unwrap(), ping);
+        }
+        assert_eq!(kdb.endpoint(), &a);
+        assert_eq!(*events.lock().unwrap(), vec![
+            FailoverEvent::Connected(a.clone()),
+            FailoverEvent::Connected(b.clone()),
+            FailoverEvent::FailedOver { from: b, to: a },
+        ]);
+    }
+
+    // spin until the current endpoint's fake q has closed its end
+    fn wait_for_hang_up(kdb: &mut FailoverKdb) {
+        while !kdb.kdb().unwrap().has_hung_up() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use super::endpoint::Endpoint;
+
+// accepts connections forever, completing the handshake and answering
+// every sync message with 1b
+pub fn spawn() -> Endpoint {
+    spawn_replying(&[255, 1]).0
+}
+
+// like spawn, but answers every sync message with `body`, and counts the
+// sync messages it gets
+pub fn spawn_replying(body: &'static [u8]) -> (Endpoint, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let queries = Arc::new(AtomicUsize::new(0));
+    let counter = queries.clone();
+    let reply = [&[1, 2, 0, 0][..], &(8 + body.len() as u32).to_le_bytes(), body].concat();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let (counter, reply) = (counter.clone(), reply.clone());
+            thread::spawn(move || {
+                let mut byte = [1;1];
+                while byte[0] != 0 {
+                    stream.read_exact(&mut byte).unwrap();
+                }
+                stream.write_all(&[6]).unwrap();
+                let mut header = [0;8];
+                while stream.read_exact(&mut header).is_ok() {
+                    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+                    stream.read_exact(&mut vec![0;len as usize - 8]).unwrap();
+                    if header[1] == 1 {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        stream.write_all(&reply).unwrap();
+                    }
+                }
+            });
+        }
+    });
+    (Endpoint::new("127.0.0.1", port, "user", "pass"), queries)
+}
+
+// accepts a single connection, answers its first sync message with 1b and
+// then hangs up, leaving nothing listening
+pub fn spawn_once() -> Endpoint {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        drop(listener);
+        let mut byte = [1;1];
+        while byte[0] != 0 {
+            stream.read_exact(&mut byte).unwrap();
+        }
+        stream.write_all(&[6]).unwrap();
+        let mut header = [0;8];
+        stream.read_exact(&mut header).unwrap();
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        stream.read_exact(&mut vec![0;len as usize - 8]).unwrap();
+        stream.write_all(&[1, 2, 0, 0, 10, 0, 0, 0, 255, 1]).unwrap();
+    });
+    Endpoint::new("127.0.0.1", port, "user", "pass")
+}
+
+// accepts a single connection and hangs up as soon as its first message
+// has arrived, without answering it, counting the messages it gets
+pub fn spawn_hanging_up() -> (Endpoint, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = received.clone();
+    thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        drop(listener);
+        let mut byte = [1;1];
+        while byte[0] != 0 {
+            stream.read_exact(&mut byte).unwrap();
+        }
+        stream.write_all(&[6]).unwrap();
+        let mut header = [0;8];
+        stream.read_exact(&mut header).unwrap();
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        stream.read_exact(&mut vec![0;len as usize - 8]).unwrap();
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    (Endpoint::new("127.0.0.1", port, "user", "pass"), received)
+}
+
+// an endpoint nothing is listening on
+pub fn dead() -> Endpoint {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    Endpoint::new("127.0.0.1", port, "user", "pass")
+}
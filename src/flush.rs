@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+/// When `Kdb::send_async` flushes its buffered writer.
+///
+/// `send_sync` and `send_response` always flush, since they need the other
+/// side to see the message straight away. Whatever the policy, a message that
+/// doesn't fit in the writer's buffer is written through immediately.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FlushPolicy {
+    /// Flush after every message. Lowest latency, and the default.
+    #[default]
+    Always,
+    /// Flush once at least this many bytes are buffered.
+    Bytes(usize),
+    /// Flush once this many messages are buffered.
+    Messages(usize),
+    /// Flush on the first send after this much time has passed since the
+    /// last flush. There is no background timer: a publisher that can go
+    /// quiet should poll `Kdb::flush_if_due` so the last messages it sent
+    /// don't sit in the buffer.
+    Interval(Duration),
+    /// Only flush when `Kdb::flush` is called or the buffer fills up.
+    Manual,
+}
+
+// tracks what has been written since the last flush
+pub(crate) struct Batch {
+    pub policy: FlushPolicy,
+    bytes: usize,
+    messages: usize,
+    last_flush: Instant,
+}
+
+impl Batch {
+    pub fn new(policy: FlushPolicy) -> Batch {
+        Batch { policy, bytes: 0, messages: 0, last_flush: Instant::now() }
+    }
+
+    // record a buffered message, returning whether the policy wants a flush
+    pub fn record(&mut self, bytes: usize) -> bool {
+        self.bytes += bytes;
+        self.messages += 1;
+        match self.policy {
+            FlushPolicy::Always => true,
+            FlushPolicy::Bytes(n) => self.bytes >= n,
+            FlushPolicy::Messages(n) => self.messages >= n,
+            FlushPolicy::Interval(d) => self.last_flush.elapsed() >= d,
+            FlushPolicy::Manual => false,
+        }
+    }
+
+    // whether buffered messages have waited out the interval
+    pub fn due(&self) -> bool {
+        match self.policy {
+            FlushPolicy::Interval(d) => self.messages > 0 && self.last_flush.elapsed() >= d,
+            _ => false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.bytes = 0;
+        self.messages = 0;
+        self.last_flush = Instant::now();
+    }
+}
@@ -1,7 +1,8 @@
 use super::kdb::Kdb;
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Error, Read, Write};
 
 pub struct Header {
+    #[allow(dead_code)]
     pub endian: Endian,
     pub protocol: u8,
     pub length: u32,
@@ -13,31 +14,39 @@ pub enum Endian {
 }
 
 impl Header {
-    pub fn read<R:Read, W:Write>(kdb: &mut Kdb<R,W>) -> Header {
+    pub fn read<R:Read, W:Write>(kdb: &mut Kdb<R,W>) -> Result<Header, Error> {
 
         let stream = kdb.reader();
         let mut endian = [0;1];
         let mut protocol = [0;1]; 
         let mut msg_length = [0;4];
 
-        stream.read_exact(&mut endian).unwrap();
-        stream.read_exact(&mut protocol).unwrap();
+        // nothing at all before the next message means the peer hung up
+        loop {
+            match stream.read(&mut endian) {
+                Ok(0) => return Err(Error::new(ErrorKind::ConnectionAborted, "connection closed by peer")),
+                Ok(_) => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        stream.read_exact(&mut protocol)?;
         // throw away two padding bytes
-        stream.read_exact(&mut [0;2]).unwrap();
-        stream.read_exact(&mut msg_length).unwrap();
+        stream.read_exact(&mut [0;2])?;
+        stream.read_exact(&mut msg_length)?;
 
         if endian[0] == 1 {
-            Header {
+            Ok(Header {
                 endian: Endian::Little,
                 protocol: u8::from_le_bytes(protocol),
                 length: u32::from_le_bytes(msg_length),
-            }
+            })
         } else {
-            Header {
+            Ok(Header {
                 endian: Endian::Big,
                 protocol: u8::from_be_bytes(protocol),
                 length: u32::from_be_bytes(msg_length),
-            }
+            })
         }
 
     }
@@ -1,12 +1,39 @@
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
 use std::io::{BufReader, BufWriter};
-use std::io::{ErrorKind, Error, Write, Read};
+use std::io::{self, ErrorKind, Error, Write, Read};
 use byteorder::{LittleEndian, WriteBytesExt};
 use crate::KObj;
 use super::header::Header;
 use super::ktype::KType;
-
-const UNSUPPORTED_TYPES: [i8;10] = [103, 104, 105, 106, 107, 108, 109, 110, 111, 112];
+use super::kvector::KVector;
+use super::enumeration::Enumeration;
+use super::decode::Decoder;
+use super::endpoint::Endpoint;
+use super::flush::{Batch, FlushPolicy};
+use super::view::KView;
+use super::lazy::{Lazy, decode_owned, decode_lenient};
+use super::buffer::BufferPool;
+use super::symbol::SymbolInterner;
+use super::text::TextMode;
+
+// sync message containing 1b, answered with 1b by any q process
+const PING: [u8;10] = [1, 1, 0, 0, 10, 0, 0, 0, 255, 1];
+
+// the most the read buffer keeps between reads, so that one large reply
+// doesn't hold on to its memory for the life of the connection
+const READ_BUF_CAP: usize = 1 << 20;
+
+// the largest message read by default, header included
+const MAX_MESSAGE_SIZE: usize = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    /// The last connection attempt or IO operation failed.
+    Failed,
+}
 
 pub struct Kdb<R: Read, W:Write> {
     host: String,
@@ -14,33 +41,105 @@ pub struct Kdb<R: Read, W:Write> {
     user: String,
     pass: String,
     reader: Option<BufReader<R>>,
-    writer: Option<BufWriter<W>>
+    writer: Option<BufWriter<W>>,
+    socket: Option<TcpStream>,
+    state: ConnectionState,
+    batch: Batch,
+    // reused by every read, and shrunk back to READ_BUF_CAP after a larger one
+    read_buf: Vec<u8>,
+    buffers: Option<BufferPool>,
+    interner: Option<SymbolInterner>,
+    text: TextMode,
+    lenient: bool,
+    max_message_size: usize,
 }
 
 impl Kdb<TcpStream, TcpStream> {
     pub fn open(&mut self) -> Result<(),Error> {
-        let mut stream = TcpStream::connect(format!("{}:{}",self.host,self.port))?;
-        let response = format!("{}:{}{}",self.user, self.pass, "\x06\x00");
-        stream.write(response.as_bytes())?;
-        stream.read_exact(&mut [0; 1])?;
+        self.state = ConnectionState::Connecting;
+        let stream = self.handshake();
+        let stream = self.check(stream)?;
         self.reader = Some(BufReader::new(stream.try_clone()?));
+        self.socket = Some(stream.try_clone()?);
         self.writer = Some(BufWriter::new(stream));
+        self.state = ConnectionState::Connected;
         Ok(())
     }
 
+    fn handshake(&self) -> Result<TcpStream, Error> {
+        let mut stream = TcpStream::connect(format!("{}:{}",self.host,self.port))?;
+        let response = format!("{}:{}{}",self.user, self.pass, "\x06\x00");
+        stream.write_all(response.as_bytes())?;
+        stream.read_exact(&mut [0; 1])?;
+        Ok(stream)
+    }
+
     pub fn new(host: &str, port: u16, user: &str, pass: &str) -> Kdb<TcpStream,TcpStream> {
+        Kdb::unconnected(host, port, user, pass)
+    }
+
+    pub fn from_endpoint(endpoint: &Endpoint) -> Kdb<TcpStream,TcpStream> {
+        Kdb::new(&endpoint.host, endpoint.port, &endpoint.user, &endpoint.pass)
+    }
+
+    // whether anything has arrived that hasn't been read yet, checked
+    // without blocking. A peer that has hung up counts, as the end of the
+    // stream is still to be read.
+    pub(crate) fn has_unread_input(&mut self) -> Result<bool, Error> {
+        if self.reader.as_ref().is_some_and(|r| !r.buffer().is_empty()) {
+            return Ok(true)
+        };
+        Ok(self.peek()?.is_some())
+    }
+
+    // whether the peer is known to have hung up, with nothing left to read
+    // before the end of the stream
+    pub(crate) fn has_hung_up(&mut self) -> bool {
+        if self.reader.as_ref().is_some_and(|r| !r.buffer().is_empty()) {
+            return false
+        };
+        !matches!(self.peek(), Ok(None) | Ok(Some(1..)))
+    }
+
+    // look at the socket without blocking: Some(0) if the peer has hung up,
+    // Some(n) if there is input waiting and None if there is nothing yet
+    fn peek(&self) -> Result<Option<usize>, Error> {
+        let socket = match self.socket.as_ref() {
+            Some(socket) => socket,
+            None => return Ok(None),
+        };
+        socket.set_nonblocking(true)?;
+        let peeked = socket.peek(&mut [0]);
+        socket.set_nonblocking(false)?;
+        match peeked {
+            Ok(n) => Ok(Some(n)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<R: Read, W: Write> Kdb<R,W> {
+
+    fn unconnected(host: &str, port: u16, user: &str, pass: &str) -> Kdb<R,W> {
         Kdb {
             host: host.to_string(),
             port,
             user: user.to_string(),
             pass: pass.to_string(),
             reader: None,
-            writer: None
+            writer: None,
+            socket: None,
+            state: ConnectionState::Disconnected,
+            batch: Batch::new(FlushPolicy::default()),
+            read_buf: vec![],
+            buffers: None,
+            interner: None,
+            text: TextMode::default(),
+            lenient: false,
+            max_message_size: MAX_MESSAGE_SIZE,
         }
     }
-}
-
-impl<R: Read, W: Write> Kdb<R,W> {
 
     pub fn reader(&mut self) -> &mut BufReader<R> {
         self.reader.as_mut().unwrap()
@@ -50,299 +149,359 @@ impl<R: Read, W: Write> Kdb<R,W> {
         self.writer.as_mut().unwrap()
     }
 
-    pub fn close(&mut self) -> Result<(), Error> {
-        self.reader = None;
-        self.writer = None;
-        Ok(())
+    // bytes written since the last flush that are still in the writer
+    pub(crate) fn buffered_output(&self) -> usize {
+        self.writer.as_ref().map_or(0, |w| w.buffer().len())
     }
 
-    pub fn send_async(&mut self, data: &KObj) -> Result<(), Error> {
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.batch.policy
+    }
+
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.batch.policy = policy;
+    }
+
+    /// Push any buffered messages out to the socket.
+    pub fn flush(&mut self) -> Result<(), Error> {
         if self.writer.is_none() {
             return Err(Error::new(ErrorKind::ConnectionRefused, "no writer available"))
         };
-        let header_bytes = vec![1, 0, 0, 0];
-        let mut data_bytes = data.serialize();
-        let type_bytes = vec![data.type_as_bytes()];
-        let mut size_bytes = vec![];
-        size_bytes.write_i32::<LittleEndian>((4 + header_bytes.len() + data_bytes.len() + type_bytes.len()) as i32)?;
-        data_bytes.splice(0..0, type_bytes);
-        data_bytes.splice(0..0, size_bytes);
-        data_bytes.splice(0..0, header_bytes);
-        let writer = self.writer();
-        writer.write(&data_bytes)?;
+        let flushed = self.writer().flush();
+        self.check(flushed)?;
+        self.batch.reset();
         Ok(())
     }
 
-    pub fn read(&mut self) -> KObj {
-        if self.reader.is_none() {
-            return KObj::Error("no reader available".to_string())
+    /// Flush if the policy is `FlushPolicy::Interval` and buffered messages
+    /// have waited longer than the interval, returning whether it flushed.
+    /// Meant to be called from a publisher's idle loop or a timer.
+    pub fn flush_if_due(&mut self) -> Result<bool, Error> {
+        if !self.batch.due() {
+            return Ok(false)
         };
-        let msg_header = Header::read(self);
-        let reader = self.reader();
+        self.flush()?;
+        Ok(true)
+    }
 
+    /// Take the bodies of `read_lazy` messages from `pool` rather than
+    /// allocating a new buffer for each one.
+    pub fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.buffers = Some(pool);
+    }
 
-        let mut msg_type = [0;1];
-        reader.read_exact(&mut msg_type).unwrap();
-        let msg_type = i8::from_le_bytes(msg_type);
+    pub fn buffer_pool(&self) -> Option<&BufferPool> {
+        self.buffers.as_ref()
+    }
 
-        if UNSUPPORTED_TYPES.contains(&msg_type){
-            // clear the buffer and return error
-            reader.read_exact(&mut vec![0;(msg_header.length - 9) as usize]).unwrap();
-            return KObj::Error(String::from("type unsupported by rsq"))
-        };
-        let data = self.read_data(msg_type);
+    /// Share one copy of each distinct symbol between the messages returned
+    /// by `read`, rather than allocating every symbol afresh. Turning it off
+    /// drops the symbols seen so far.
+    pub fn set_symbol_interning(&mut self, enabled: bool) {
+        self.interner = if enabled { Some(self.interner.take().unwrap_or_default()) } else { None };
+    }
 
-        if msg_header.protocol == 1 {
-            self.send_response(&KObj::Atom(KType::Boolean(true))).unwrap();
-        };
+    pub fn symbol_interner(&mut self) -> Option<&mut SymbolInterner> {
+        self.interner.as_mut()
+    }
 
-        data
+    /// Choose how `read` handles symbols and chars that aren't UTF-8.
+    ///
+    /// Only `TextMode::Strict` reads tables in parallel with the `parallel`
+    /// feature; the other modes decode every message on one thread.
+    pub fn set_text_mode(&mut self, text: TextMode) {
+        self.text = text;
+    }
 
+    pub fn text_mode(&self) -> TextMode {
+        self.text
     }
 
-    fn extract_atom(&mut self, len: usize) -> Vec<u8> {
-        let mut vec = vec![0;len];
-        self.reader().read_exact(&mut vec).unwrap();
-        vec
+    /// Have `read` return messages holding a type rsq can't read, e.g. a
+    /// foreign (112), as `KObj::Opaque` rather than failing, so they can be
+    /// forwarded or recorded as they were sent.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
     }
 
-    fn extract_string(&mut self) -> Vec<u8> {
-        let stream = self.reader();
-        stream.read_exact(&mut [0;1]).unwrap(); // discard attribute
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
 
-        let mut len = [0;4];
-        stream.read_exact(&mut len).unwrap();
-        let len = u32::from_le_bytes(len) as usize;
+    /// Refuse messages longer than `size` bytes, header included, rather
+    /// than allocating whatever a corrupt header asks for. They are skipped
+    /// and reported as `ErrorKind::InvalidData`. The default is 1 GiB.
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
 
-        let mut string = vec![0;len];
-        stream.read_exact(&mut string).unwrap();
-        string
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
     }
 
-    fn extract_sym(&mut self) -> Vec<u8> {
-        let stream = self.reader();
-        let mut sym = vec![];
-        let mut bit = [1;1];
-        loop {
-            stream.read_exact(&mut bit).unwrap();
-            if bit[0] == 0 { break };
-            sym.push(bit[0]);
-        }
-        sym
-    }
-
-    fn read_atom(&mut self, ktype: KType) -> KObj {
-        let vec_data = match ktype {
-            KType::Boolean(_)   => self.extract_atom(1),
-            KType::Guid(_)      => self.extract_atom(16),
-            KType::Byte(_)      => self.extract_atom(1),
-            KType::Short(_)     => self.extract_atom(2),
-            KType::Int(_)       => self.extract_atom(4),
-            KType::Long(_)      => self.extract_atom(8),
-            KType::Real(_)      => self.extract_atom(4),
-            KType::Float(_)     => self.extract_atom(8),
-            KType::Char(_)      => self.extract_atom(1),
-            KType::String(_)    => self.extract_string(),
-            KType::Symbol(_)    => self.extract_sym(),
-            KType::Timestamp(_) => self.extract_atom(8),
-            KType::Month(_)     => self.extract_atom(4),
-            KType::Date(_)      => self.extract_atom(4),
-            KType::Datetime(_)  => self.extract_atom(8),
-            KType::Timespan(_)  => self.extract_atom(8),
-            KType::Minute(_)    => self.extract_atom(4),
-            KType::Second(_)    => self.extract_atom(4),
-            KType::Time(_)      => self.extract_atom(4),
-            KType::Unary(_)     => self.extract_atom(1),
-            KType::Operator(_)  => self.extract_atom(1),
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == ConnectionState::Connected
+    }
+
+    /// Flush any buffered messages and shut the socket down. The connection
+    /// is released even if flushing fails, and the first error is returned.
+    pub fn close(&mut self) -> Result<(), Error> {
+        let flushed = match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
         };
-        KObj::Atom(ktype).deserialize(&vec_data)
-    }
-
-    fn read_uniform_list(&mut self, msg_type: i8, len: u32) -> KObj {
-        let mut list = vec![];
-        for _ in 0..len {
-            let data = self.read_data(-1 * msg_type);
-            list.push(data);
-        };  
-        KObj::List(list)
-    }
-
-    fn read_generic_list(&mut self, len:u32) -> KObj {
-        let mut list = vec![];
-        for _ in 0..len{
-            let mut msg_type = [0;1];
-            self.reader().read_exact(&mut msg_type).unwrap();
-            let msg_code = i8::from_le_bytes(msg_type);
-            list.push(self.read_data(msg_code));
-        };  
-        KObj::GenericList(list)
-    }  
-
-    fn read_list(&mut self, msg_type: i8) -> KObj {
-        let mut attr = [0;1];
-        self.reader().read_exact(&mut attr).unwrap(); // throw away attribute for now
-        let mut len = [0;4];                     // extract vector length
-        self.reader().read_exact(&mut len).unwrap();
-        let len = u32::from_le_bytes(len);
-        if msg_type == 0 {
-            self.read_generic_list(len)
-        } else {
-            self.read_uniform_list(msg_type, len)
+        let shutdown = match self.socket.take() {
+            Some(socket) => socket.shutdown(Shutdown::Both),
+            None => Ok(()),
+        };
+        self.reader = None;
+        self.writer = None;
+        self.batch.reset();
+        self.state = ConnectionState::Disconnected;
+        flushed?;
+        match shutdown {
+            // the peer got there first
+            Err(e) if e.kind() == ErrorKind::NotConnected => Ok(()),
+            result => result,
         }
     }
 
-    fn read_dict(&mut self) -> KObj {
-
-        let mut key_type = [0;1];
-        self.reader().read_exact(&mut key_type).unwrap();
-        let key_type = i8::from_le_bytes(key_type);
+    // mark the connection as failed if an IO operation on it went wrong
+    fn check<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        if result.is_err() {
+            self.state = ConnectionState::Failed;
+        };
+        result
+    }
 
-        let keys = self.read_data(key_type);
+    /// Round trip a small sync message to check the connection is alive.
+    /// Unlike `read`, every failure is reported as an error rather than a panic.
+    ///
+    /// The next message to arrive has to be the reply, so a connection with
+    /// unread messages waiting fails the ping with `ErrorKind::InvalidData`
+    /// and is left marked as failed.
+    pub fn ping(&mut self) -> Result<(), Error> {
+        if self.writer.is_none() || self.reader.is_none() {
+            return Err(Error::new(ErrorKind::ConnectionRefused, "no connection available"))
+        };
+        let written = self.writer().write_all(&PING);
+        self.check(written)?;
+        self.flush()?;
+        let response = self.read_ping_response();
+        self.check(response)
+    }
 
-        let mut val_type = [0;1];
-        self.reader().read_exact(&mut val_type).unwrap();
-        let val_type = i8::from_le_bytes(val_type);
-        let vals = self.read_data(val_type);
+    fn read_ping_response(&mut self) -> Result<(), Error> {
+        let msg_header = Header::read(self)?;
+        let mut body = std::mem::take(&mut self.read_buf);
+        let read = self.read_message(&msg_header, &mut body);
+        let is_response = msg_header.protocol == 2;
+        let is_error = body.first() == Some(&(-128i8 as u8));
+        let is_boolean = body.len() == 2 && body[0] == -1i8 as u8;
+        self.keep_read_buf(body);
+        read?;
+        if is_response && is_error {
+            return Err(Error::other("ping returned an error"))
+        };
+        // e.g. an update published before the ping was sent, which leaves
+        // the real reply still to come
+        if !(is_response && is_boolean) {
+            return Err(Error::new(ErrorKind::InvalidData, "ping was answered by a message that isn't its reply"))
+        };
+        Ok(())
+    }
 
-        let keys: Vec<KObj> = match keys {
-             KObj::List(k) => k,
-            _ => return KObj::Error("keys of dictionary must be a list".to_string()) // this shouldn't happen
+    pub fn send_async(&mut self, data: &KObj) -> Result<(), Error> {
+        if self.writer.is_none() {
+            return Err(Error::new(ErrorKind::ConnectionRefused, "no writer available"))
+        };
+        data.check_encodable()?;
+        let written = self.write_message(0, data);
+        let len = self.check(written)?;
+        if self.batch.record(len) {
+            self.flush()?;
         };
+        Ok(())
+    }
 
-        let vals = match vals {
-            KObj::List(k) => k,
-            KObj::GenericList(k) => k,
-           _ => return KObj::Error("keys of dictionary must be a list".to_string()) // this shouldn't happen
+    /// Read the next message from the connection.
+    ///
+    /// If the other side closed the connection cleanly between messages the
+    /// error is `ErrorKind::ConnectionAborted`, whereas a message cut off
+    /// part-way through is `ErrorKind::UnexpectedEof`.
+    pub fn read(&mut self) -> Result<KObj, Error> {
+        if self.reader.is_none() {
+            return Err(Error::new(ErrorKind::ConnectionRefused, "no reader available"))
         };
-        
-        KObj::Dict(keys, vals)
-        
+        let mut body = std::mem::take(&mut self.read_buf);
+        let read = self.read_body(&mut body);
+        let msg = read.and_then(|()| match self.lenient {
+            true => decode_lenient(&body, self.interner.as_mut(), self.text),
+            false => decode_owned(&body, self.interner.as_mut(), self.text),
+        });
+        self.keep_read_buf(body);
+        self.track(msg)
     }
 
-    fn read_table(&mut self) -> KObj {
+    /// Read the next message into `buf` and decode it in place, borrowing
+    /// symbols, strings and vector data from `buf` rather than copying them.
+    ///
+    /// `buf` is cleared first, so one buffer can be reused for every message.
+    pub fn read_view<'b>(&mut self, buf: &'b mut Vec<u8>) -> Result<KView<'b>, Error> {
+        if self.reader.is_none() {
+            return Err(Error::new(ErrorKind::ConnectionRefused, "no reader available"))
+        };
+        let read = self.read_body(buf);
+        let body: &'b Vec<u8> = buf;
+        let msg = read.and_then(|()| Decoder::new(body).decode());
+        self.track(msg)
+    }
 
-        let mut key_type = [0;1];
-        self.reader().read_exact(&mut key_type).unwrap();
-        let key_type = i8::from_le_bytes(key_type);
+    /// Read the next message, leaving tables and dictionaries undecoded until
+    /// their columns are asked for. Useful for wide tables where only a few
+    /// columns are needed.
+    pub fn read_lazy(&mut self) -> Result<Lazy, Error> {
+        if self.reader.is_none() {
+            return Err(Error::new(ErrorKind::ConnectionRefused, "no reader available"))
+        };
+        let mut body = self.buffers.as_ref().map(BufferPool::get).unwrap_or_default();
+        let read = self.read_body(&mut body);
+        let msg = read.and_then(|()| Lazy::new(body));
+        self.track(msg)
+    }
 
-        let keys = self.read_data(key_type);
+    // put the read buffer back for the next read, shrinking it if the last
+    // message grew it past READ_BUF_CAP
+    fn keep_read_buf(&mut self, mut body: Vec<u8>) {
+        if body.capacity() > READ_BUF_CAP {
+            body.clear();
+            body.shrink_to(READ_BUF_CAP);
+        };
+        self.read_buf = body;
+    }
 
-        let mut val_type = [0;1];
-        self.reader().read_exact(&mut val_type).unwrap();
-        let val_type = i8::from_le_bytes(val_type);
-        let vals = self.read_data(val_type);
+    // update the connection state after a read
+    fn track<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        if let Err(e) = &result {
+            self.state = match e.kind() {
+                ErrorKind::ConnectionAborted => ConnectionState::Disconnected,
+                // the bad message has been skipped, so the connection is fine
+                ErrorKind::InvalidData | ErrorKind::Unsupported => self.state,
+                _ => ConnectionState::Failed,
+            };
+        };
+        result
+    }
 
-        let keys: Vec<KObj> = match keys {
-             KObj::List(k) => k,
-            _ => return KObj::Error("keys of dictionary must be a list".to_string()) // this shouldn't happen
+    // read the next message body into `buf`, answering it if it is sync
+    fn read_body(&mut self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let msg_header = Header::read(self)?;
+        let read = self.read_message(&msg_header, buf);
+        if let Err(e) = &read {
+            if e.kind() != ErrorKind::InvalidData {
+                return read
+            };
         };
 
-        let vals = match vals {
-            KObj::List(k) => k,
-            KObj::GenericList(k) => k,
-           _ => return KObj::Error("keys of dictionary must be a list".to_string()) // this shouldn't happen
+        if msg_header.protocol == 1 {
+            self.send_response(&KObj::Atom(KType::Boolean(true)))?;
         };
-        
-        KObj::Table(keys, vals)
-     
-    }
-
-    fn read_lambda(&mut self) -> KObj {
-        let stream = self.reader();
-        stream.read_exact(&mut [0;3]).unwrap();
-        let mut len = [0;4];
-        stream.read_exact(&mut len).unwrap();
-        let len = i32::from_le_bytes(len) as usize;
-        let mut lambda = vec![0;len];
-        stream.read_exact(&mut lambda).unwrap();
-        KObj::Lambda(String::from_utf8(lambda).unwrap())
-    }
-
-    fn read_error(&mut self) -> KObj {
-        let error_msg = self.extract_sym();
-        KObj::Error(String::from_utf8(error_msg.to_vec()).unwrap())
-    }
-
-    fn read_data(&mut self, msg_type: i8) -> KObj {
-        let mut kobj = KObj::new(msg_type);
-        kobj = match kobj {
-            KObj::Atom(k) => self.read_atom(k),
-            KObj::List(_) => self.read_list(msg_type),
-            KObj::GenericList(_) => self.read_list(msg_type),
-            KObj::Dict(_,_) => self.read_dict(),
-            KObj::Table(_,_) => {
-                self.reader().read_exact(&mut[0;2]).unwrap();
-                self.read_table()
-            },
-            KObj::Lambda(_) => {
-                self.read_lambda()
-            }
-            KObj::Error(_) => {
-                self.read_error()
-            }
+
+        read
+    }
+
+    // read the body that follows `msg_header` into `buf`, or skip past it if
+    // the message is over the maximum size
+    fn read_message(&mut self, msg_header: &Header, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let len = msg_header.length as usize;
+        let body_len = len.checked_sub(8)
+            // can't tell where the next message starts, so this one can't be skipped
+            .ok_or_else(|| Error::other("message length is shorter than its header"))?;
+        buf.clear();
+        if len > self.max_message_size {
+            let skipped = io::copy(&mut self.reader().take(body_len as u64), &mut io::sink())?;
+            if skipped < body_len as u64 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed mid-message"))
+            };
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("message of {} bytes is over the maximum size of {}", len, self.max_message_size)))
         };
-        kobj
+        // pull in the whole message so a bad one can be skipped cleanly
+        buf.resize(body_len, 0);
+        self.reader().read_exact(buf)
     }
 
     pub fn send_sync(&mut self, data: &KObj) -> Result<KObj, Error> {
         if self.writer.is_none() {
             return Err(Error::new(ErrorKind::ConnectionRefused, "no writer available"))
         };
-        let header_bytes = vec![1, 1, 0, 0];
-        let mut data_bytes = data.serialize();
-        let type_bytes = vec![data.type_as_bytes()];
-        let mut size_bytes = vec![];
-        size_bytes.write_i32::<LittleEndian>((4 + header_bytes.len() + data_bytes.len() + type_bytes.len()) as i32).unwrap();
-        data_bytes.splice(0..0, type_bytes);
-        data_bytes.splice(0..0, size_bytes);
-        data_bytes.splice(0..0, header_bytes);
-        // println!("{:?}", data_bytes);
-        self.writer().write(&data_bytes).unwrap();
-        self.writer().flush().unwrap(); 
-        let response = self.read();
-        Ok(response)
+        data.check_encodable()?;
+        let written = self.write_message(1, data);
+        self.check(written)?;
+        self.flush()?;
+        self.read()
+    }
+
+    // write the header, then stream the value in after it, returning the
+    // size of the whole message
+    fn write_message(&mut self, protocol: u8, data: &KObj) -> Result<usize, Error> {
+        let len = 8 + 1 + data.serialized_len();
+        let writer = self.writer();
+        writer.write_all(&[1, protocol, 0, 0])?;
+        writer.write_u32::<LittleEndian>(len as u32)?;
+        writer.write_u8(data.type_as_bytes())?;
+        data.serialize_into(writer)?;
+        Ok(len)
+    }
+
+    /// Look an enumeration's indices up in its domain, which is fetched with
+    /// a sync query for its name.
+    pub fn resolve_enum(&mut self, enumeration: &Enumeration) -> Result<KObj, Error> {
+        match self.send_sync(&KObj::string(&enumeration.domain))? {
+            KObj::Vector(_, KVector::Symbol(symbols)) => enumeration.resolve(&symbols),
+            KObj::Error(e) => Err(Error::other(e)),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("`{} is not a symbol list", enumeration.domain))),
+        }
     }
 
     pub fn send_response(&mut self, data: &KObj) -> Result<(), Error> {
         if self.writer.is_none() {
             return Err(Error::new(ErrorKind::ConnectionRefused, "no writer available"))
         };
-        let header_bytes = vec![1, 2, 0, 0];
-        let mut data_bytes = data.serialize();
-        let type_bytes = vec![data.type_as_bytes()];
-        let mut size_bytes = vec![];
-        size_bytes.write_i32::<LittleEndian>((4 + header_bytes.len() + data_bytes.len() + type_bytes.len()) as i32).unwrap();
-        data_bytes.splice(0..0, type_bytes);
-        data_bytes.splice(0..0, size_bytes);
-        data_bytes.splice(0..0, header_bytes);
-        self.writer().write(&data_bytes).unwrap();
-        self.writer().flush().unwrap();    
+        data.check_encodable()?;
+        let written = self.write_message(2, data);
+        self.check(written)?;
+        self.flush()?;
         Ok(())
     }
 }
 
 #[cfg(test)]
-mod atom_tests {
+mod test_util {
     use super::*;
-    use uuid::Uuid;
 
-    fn setup_kdb() -> Kdb<&'static [u8], Vec<u8>> {
-        Kdb {
-            host: "localhost".to_string(),
-            port: 1234,
-            user: "user".to_string(),
-            pass: "pass".to_string(),
-            reader: None,
-            writer: None
-        }
+    pub fn setup_kdb<'a>() -> Kdb<&'a [u8], Vec<u8>> {
+        Kdb::unconnected("localhost", 1234, "user", "pass")
     }
+}
+
+#[cfg(test)]
+mod atom_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+    use uuid::Uuid;
+    use crate::Symbol;
 
     #[test]
     fn read_boolean() {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 10, 0, 0, 0, 255, 1];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Boolean(true)));
     }
 
@@ -352,7 +511,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Boolean(true))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 10, 0, 0, 0, 255, 1]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 10, 0, 0, 0, 255, 1]);
     }
 
     #[test]
@@ -360,7 +519,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 25, 0, 0, 0, 254, 204, 69, 164, 216, 188, 197, 0, 49, 235, 69, 53, 147, 191, 81, 97, 76];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Guid(Uuid::parse_str("cc45a4d8-bcc5-0031-eb45-3593bf51614c").unwrap())));
     }
 
@@ -370,7 +529,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Guid(Uuid::parse_str("cc45a4d8-bcc5-0031-eb45-3593bf51614c").unwrap()))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 25, 0, 0, 0, 254, 204, 69, 164, 216, 188, 197, 0, 49, 235, 69, 53, 147, 191, 81, 97, 76]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 25, 0, 0, 0, 254, 204, 69, 164, 216, 188, 197, 0, 49, 235, 69, 53, 147, 191, 81, 97, 76]);
     }
 
     #[test]
@@ -378,7 +537,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 10, 0, 0, 0, 252, 2];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Byte(2)));
     }
 
@@ -388,7 +547,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 10, 0, 0, 0, 252, 2]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 10, 0, 0, 0, 252, 2]);
     }
 
     #[test]
@@ -396,7 +555,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 11, 0, 0, 0, 251, 160, 2];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Short(672)));
     }
 
@@ -406,7 +565,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Short(672))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 11, 0, 0, 0, 251, 160, 2]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 11, 0, 0, 0, 251, 160, 2]);
     }
 
     #[test]
@@ -414,7 +573,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 13, 0, 0, 0, 250, 170, 253, 255, 255];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Int(-598)));
     }
 
@@ -424,7 +583,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Int(-598))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 13, 0, 0, 0, 250, 170, 253, 255, 255]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 13, 0, 0, 0, 250, 170, 253, 255, 255]);
     }
 
     #[test]
@@ -432,7 +591,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 17, 0, 0, 0, 249, 22, 91, 146, 10, 33, 2, 0, 0];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Long(2_340_934_540_054)));
     }
 
@@ -442,7 +601,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Long(2_340_934_540_054))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 17, 0, 0, 0, 249, 22, 91, 146, 10, 33, 2, 0, 0]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 17, 0, 0, 0, 249, 22, 91, 146, 10, 33, 2, 0, 0]);
     }
 
     #[test]
@@ -450,7 +609,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 13, 0, 0, 0, 248, 10, 215, 157, 66];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Real(78.92)));
     }
 
@@ -460,7 +619,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Real(78.92))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 13, 0, 0, 0, 248, 10, 215, 157, 66]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 13, 0, 0, 0, 248, 10, 215, 157, 66]);
     }
 
     #[test]
@@ -468,7 +627,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 17, 0, 0, 0, 247, 76, 55, 137, 65, 213, 77, 247, 64];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Float(95453.3285)));
     }
 
@@ -478,7 +637,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Float(95453.3285))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 17, 0, 0, 0, 247, 76, 55, 137, 65, 213, 77, 247, 64]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 17, 0, 0, 0, 247, 76, 55, 137, 65, 213, 77, 247, 64]);
     }
 
     #[test]
@@ -486,7 +645,7 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 10, 0, 0, 0, 246, 36];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
+        let data = kdb.read().unwrap();
         assert_eq!(data, KObj::Atom(KType::Char('$')));
     }
 
@@ -496,7 +655,7 @@ mod atom_tests {
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
         kdb.send_async(&KObj::Atom(KType::Char('$'))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 10, 0, 0, 0, 246, 36]);
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 10, 0, 0, 0, 246, 36]);
     }
 
     #[test]
@@ -504,8 +663,9 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 18, 0, 0, 0, 10, 0, 4, 0, 0, 0, 240, 159, 152, 130];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
-        assert_eq!(data, KObj::Atom(KType::String("😂".to_string())));
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::string("😂"));
+        assert_eq!(data.as_str(), Some("😂"));
     }
 
     #[test]
@@ -513,8 +673,18 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
-        kdb.send_async(&KObj::Atom(KType::String("😂".to_string()))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 18, 0, 0, 0, 10, 0, 4, 0, 0, 0, 240, 159, 152, 130]);
+        kdb.send_async(&KObj::string("😂")).unwrap();
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 18, 0, 0, 0, 10, 0, 4, 0, 0, 0, 240, 159, 152, 130]);
+    }
+
+    #[test]
+    fn list_of_strings_round_trips() {
+        // ("ab";enlist "c")
+        let data = KObj::GenericList(vec![KObj::string("ab"), KObj::string("c")]);
+        let bytes = [0, 0, 2, 0, 0, 0, 10, 0, 2, 0, 0, 0, 97, 98, 10, 0, 1, 0, 0, 0, 99];
+        assert_eq!(data.serialize(), &bytes[1..]);
+        assert_eq!(KView::decode(&bytes).unwrap().into_kobj(), data);
+        assert_eq!(format!("{}", data), "(\"ab\";(enlist \"c\"))");
     }
 
     #[test]
@@ -522,8 +692,8 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![1, 0, 0, 0, 15, 0, 0, 0, 245, 104, 101, 108, 108, 111, 0];
         kdb.reader = Some(BufReader::new(byte_data.as_slice()));
-        let data = kdb.read();
-        assert_eq!(data, KObj::Atom(KType::Symbol(String::from("hello"))));
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::Atom(KType::Symbol(Symbol::from("hello"))));
     }
 
     #[test]
@@ -531,7 +701,664 @@ mod atom_tests {
         let mut kdb = setup_kdb();
         let byte_data = vec![];
         kdb.writer = Some(BufWriter::new(byte_data));
-        kdb.send_async(&KObj::Atom(KType::Symbol(String::from("hello")))).unwrap();
-        assert_eq!(kdb.writer.unwrap().buffer(), vec![1, 0, 0, 0, 15, 0, 0, 0, 245, 104, 101, 108, 108, 111, 0]);
+        kdb.send_async(&KObj::Atom(KType::Symbol(Symbol::from("hello")))).unwrap();
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 15, 0, 0, 0, 245, 104, 101, 108, 108, 111, 0]);
+    }
+}
+
+#[cfg(test)]
+mod eof_tests {
+    use super::*;
+
+    fn setup_kdb(byte_data: &'static [u8]) -> Kdb<&'static [u8], Vec<u8>> {
+        let mut kdb = test_util::setup_kdb();
+        kdb.reader = Some(BufReader::new(byte_data));
+        kdb
+    }
+
+    #[test]
+    fn closed_between_messages() {
+        let mut kdb = setup_kdb(&[1, 0, 0, 0, 10, 0, 0, 0, 252, 2]);
+        assert_eq!(kdb.read().unwrap(), KObj::Atom(KType::Byte(2)));
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::ConnectionAborted);
+        assert_eq!(kdb.state(), ConnectionState::Disconnected);
+    }
+
+    #[test]
+    fn closed_mid_header() {
+        let mut kdb = setup_kdb(&[1, 0, 0]);
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(kdb.state(), ConnectionState::Failed);
+    }
+
+    #[test]
+    fn bad_message_is_skipped() {
+        // a symbol missing its terminator, then a byte
+        let mut kdb = setup_kdb(&[1, 0, 0, 0, 11, 0, 0, 0, 245, 104, 105, 1, 0, 0, 0, 10, 0, 0, 0, 252, 2]);
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(kdb.read().unwrap(), KObj::Atom(KType::Byte(2)));
+    }
+
+    #[test]
+    fn oversized_message_is_skipped() {
+        // a long, then a byte
+        let mut kdb = setup_kdb(&[1, 0, 0, 0, 17, 0, 0, 0, 249, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 10, 0, 0, 0, 252, 2]);
+        kdb.set_max_message_size(16);
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(kdb.state(), ConnectionState::Disconnected);
+        assert_eq!(kdb.read().unwrap(), KObj::Atom(KType::Byte(2)));
+    }
+
+    #[test]
+    fn closed_mid_message() {
+        let mut kdb = setup_kdb(&[1, 0, 0, 0, 17, 0, 0, 0, 249, 22, 91, 146]);
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+}
+
+#[cfg(test)]
+mod flush_tests {
+    use super::*;
+    use crate::FlushPolicy;
+    use std::time::Duration;
+
+    fn setup_kdb(policy: FlushPolicy) -> Kdb<&'static [u8], Vec<u8>> {
+        let mut kdb = test_util::setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        kdb.set_flush_policy(policy);
+        kdb
+    }
+
+    #[test]
+    fn flush_every_message() {
+        let mut kdb = setup_kdb(FlushPolicy::Always);
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        assert_eq!(kdb.writer().get_ref().len(), 10);
+    }
+
+    #[test]
+    fn flush_after_messages() {
+        let mut kdb = setup_kdb(FlushPolicy::Messages(2));
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        assert_eq!(kdb.writer().get_ref().len(), 0);
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        assert_eq!(kdb.writer().get_ref().len(), 20);
+    }
+
+    #[test]
+    fn flush_after_bytes() {
+        let mut kdb = setup_kdb(FlushPolicy::Bytes(25));
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        assert_eq!(kdb.writer().get_ref().len(), 0);
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        assert_eq!(kdb.writer().get_ref().len(), 30);
+    }
+
+    #[test]
+    fn flush_when_interval_is_due() {
+        let mut kdb = setup_kdb(FlushPolicy::Interval(Duration::from_millis(200)));
+        assert!(!kdb.flush_if_due().unwrap());
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        assert!(!kdb.flush_if_due().unwrap());
+        assert_eq!(kdb.writer().get_ref().len(), 0);
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(kdb.flush_if_due().unwrap());
+        assert_eq!(kdb.writer().get_ref().len(), 10);
+        // nothing left to flush
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(!kdb.flush_if_due().unwrap());
+    }
+
+    #[test]
+    fn manual_flush() {
+        let mut kdb = setup_kdb(FlushPolicy::Manual);
+        kdb.send_async(&KObj::Atom(KType::Byte(2))).unwrap();
+        assert_eq!(kdb.writer().get_ref().len(), 0);
+        kdb.flush().unwrap();
+        assert_eq!(kdb.writer().get_ref().len(), 10);
+    }
+}
+
+#[cfg(test)]
+mod connection_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+    use crate::fake_q;
+
+    #[test]
+    fn open_and_close() {
+        let mut kdb = Kdb::from_endpoint(&fake_q::spawn());
+        assert_eq!(kdb.state(), ConnectionState::Disconnected);
+        kdb.open().unwrap();
+        assert!(kdb.is_connected());
+        kdb.close().unwrap();
+        assert_eq!(kdb.state(), ConnectionState::Disconnected);
+        assert!(kdb.send_async(&KObj::Atom(KType::Byte(2))).is_err());
+    }
+
+    #[test]
+    fn ping_needs_its_reply() {
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        kdb.reader = Some(BufReader::new(&[1, 2, 0, 0, 10, 0, 0, 0, 255, 1]));
+        kdb.ping().unwrap();
+        // an async 1b published before the reply arrived
+        kdb.reader = Some(BufReader::new(&[1, 0, 0, 0, 10, 0, 0, 0, 255, 1, 1, 2, 0, 0, 10, 0, 0, 0, 255, 1]));
+        assert_eq!(kdb.ping().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(kdb.state(), ConnectionState::Failed);
+        // a reply that isn't a boolean
+        kdb.reader = Some(BufReader::new(&[1, 2, 0, 0, 10, 0, 0, 0, 252, 1]));
+        assert_eq!(kdb.ping().unwrap_err().kind(), ErrorKind::InvalidData);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn failed_open() {
+        let mut kdb = Kdb::from_endpoint(&fake_q::dead());
+        assert!(kdb.open().is_err());
+        assert_eq!(kdb.state(), ConnectionState::Failed);
+    }
+}
+
+#[cfg(test)]
+mod list_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+    use crate::Attribute;
+
+    #[test]
+    fn read_long_vector() {
+        let mut kdb = setup_kdb();
+        let byte_data = vec![1, 0, 0, 0, 38, 0, 0, 0, 7, 0, 3, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0];
+        kdb.reader = Some(BufReader::new(byte_data.as_slice()));
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::Vector(Attribute::None, KVector::Long(vec![1, 2, 3])));
+        assert_eq!(format!("{}", data), "(1j;2j;3j)");
+    }
+
+    #[test]
+    fn write_long_vector() {
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        kdb.send_async(&KObj::Vector(Attribute::None, KVector::Long(vec![1, 2, 3]))).unwrap();
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 38, 0, 0, 0, 7, 0, 3, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn write_generic_list() {
+        // (1 2j;`a)
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        let data = KObj::GenericList(vec![KObj::Vector(Attribute::None, KVector::Long(vec![1, 2])), KObj::Atom(KType::Symbol("a".into()))]);
+        assert_eq!(data.serialized_len(), data.serialize().len());
+        kdb.send_async(&data).unwrap();
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 39, 0, 0, 0, 0, 0, 2, 0, 0, 0,
+            7, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 245, 97, 0]);
+    }
+
+    #[test]
+    fn sorted_vector_round_trips() {
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        let data = KObj::Vector(Attribute::Sorted, KVector::Long(vec![1, 2]));
+        kdb.send_async(&data).unwrap();
+        let written = kdb.writer.take().unwrap().into_inner().unwrap();
+        assert_eq!(&written[8..11], &[7, 1, 2]);
+        assert_eq!(KView::decode(&written[8..]).unwrap().into_kobj(), data);
+        assert_eq!(format!("{}", data), "`s#(1j;2j)");
+    }
+
+    #[test]
+    fn keeps_column_attributes() {
+        // ([] sym:`p#`a`a)
+        let body = vec![98, 0, 99, 11, 0, 1, 0, 0, 0, 115, 121, 109, 0, 0, 0, 1, 0, 0, 0, 11, 3, 2, 0, 0, 0, 97, 0, 97, 0];
+        let table = KView::decode(&body).unwrap().into_kobj();
+        assert_eq!(table, KObj::Table(
+            vec![KObj::Atom(KType::Symbol("sym".into()))],
+            vec![KObj::Vector(Attribute::Parted, KVector::Symbol(vec!["a".into(), "a".into()]))]
+        ));
+        assert_eq!(table.serialize(), &body[1..]);
+    }
+
+    #[test]
+    fn rejects_unknown_attribute() {
+        let body = vec![7, 9, 0, 0, 0, 0];
+        assert_eq!(KView::decode(&body).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn write_empty_typed_list() {
+        let empty = KObj::typed_list(7, vec![]).unwrap();
+        assert_eq!(empty.serialize(), vec![0, 0, 0, 0, 0]);
+        assert_eq!(empty.type_as_bytes(), 7);
+        assert_eq!(format!("{}", empty), "`long$()");
+        assert_eq!(KView::decode(&[7, 0, 0, 0, 0, 0]).unwrap().into_kobj(), KObj::Vector(Attribute::None, KVector::Long(vec![])));
+    }
+
+    #[test]
+    fn mixed_lists_go_out_as_general_lists() {
+        let items = vec![KObj::Atom(KType::Long(1)), KObj::Atom(KType::Symbol("a".into()))];
+        assert_eq!(KObj::list(items.clone()), KObj::GenericList(items.clone()));
+        assert_eq!(KObj::typed_list(7, items.clone()).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(KObj::List(7, items.clone()).serialize(), KObj::GenericList(items).serialize());
+        let longs = KObj::list(vec![KObj::Atom(KType::Long(1)), KObj::Atom(KType::Long(2))]);
+        assert_eq!(longs.type_as_bytes(), 7);
+        assert!(KObj::typed_list(3, vec![]).is_err());
+    }
+
+    #[test]
+    fn read_symbol_vector() {
+        let mut kdb = setup_kdb();
+        let byte_data = vec![1, 0, 0, 0, 19, 0, 0, 0, 11, 0, 2, 0, 0, 0, 97, 98, 0, 99, 0];
+        kdb.reader = Some(BufReader::new(byte_data.as_slice()));
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::Vector(Attribute::None, KVector::Symbol(vec!["ab".into(), "c".into()])));
+    }
+
+    #[test]
+    fn read_table() {
+        // ([] sym:`a`b; px:1 2f)
+        let mut kdb = setup_kdb();
+        let byte_data = vec![1, 0, 0, 0, 62, 0, 0, 0, 98, 0, 99, 11, 0, 2, 0, 0, 0, 115, 121, 109, 0, 112, 120, 0,
+            0, 0, 2, 0, 0, 0, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0, 9, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 0, 64];
+        kdb.reader = Some(BufReader::new(byte_data.as_slice()));
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::Table(
+            vec![KObj::Atom(KType::Symbol("sym".into())), KObj::Atom(KType::Symbol("px".into()))],
+            vec![KObj::Vector(Attribute::None, KVector::Symbol(vec!["a".into(), "b".into()])), KObj::Vector(Attribute::None, KVector::Float(vec![1., 2.]))]
+        ));
+        assert_eq!(format!("{}", data), "flip (`sym;`px)!((`a;`b);(1f;2f))");
+    }
+
+    #[test]
+    fn write_table() {
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        let table = KObj::Table(
+            vec![KObj::Atom(KType::Symbol("sym".into())), KObj::Atom(KType::Symbol("px".into()))],
+            vec![KObj::Vector(Attribute::None, KVector::Symbol(vec!["a".into(), "b".into()])), KObj::Vector(Attribute::None, KVector::Float(vec![1., 2.]))]
+        );
+        assert_eq!(table.encoded_len(), 54);
+        kdb.send_async(&table).unwrap();
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 62, 0, 0, 0, 98, 0, 99, 11, 0, 2, 0, 0, 0, 115, 121, 109, 0, 112, 120, 0,
+            0, 0, 2, 0, 0, 0, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0, 9, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 0, 64]);
+    }
+
+    #[test]
+    fn empty_table_round_trips() {
+        // flip `symbol$()!()
+        let table = KObj::Table(vec![], vec![]);
+        let bytes = [98, 0, 99, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(table.serialize(), &bytes[1..]);
+        assert_eq!(table.encoded_len(), bytes.len());
+        assert_eq!(KView::decode(&bytes).unwrap().into_kobj(), table);
+        assert_eq!(Decoder::new(&bytes).decode_owned(TextMode::Strict, None).unwrap(), table);
+    }
+
+    #[test]
+    fn write_dict() {
+        // `a`b!(1j;`c)
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        let dict = KObj::dict(
+            KObj::list(vec![KObj::Atom(KType::Symbol("a".into())), KObj::Atom(KType::Symbol("b".into()))]),
+            KObj::list(vec![KObj::Atom(KType::Long(1)), KObj::Atom(KType::Symbol("c".into()))])
+        );
+        kdb.send_async(&dict).unwrap();
+        let written = kdb.writer.unwrap().into_inner().unwrap();
+        assert_eq!(written, vec![1, 0, 0, 0, 37, 0, 0, 0, 99, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0,
+            0, 0, 2, 0, 0, 0, 249, 1, 0, 0, 0, 0, 0, 0, 0, 245, 99, 0]);
+        assert_eq!(written.len(), 8 + dict.encoded_len());
+    }
+
+    #[test]
+    fn sorted_dict_keeps_its_attribute() {
+        // `s#`a`b!1 2
+        let dict = KObj::dict(
+            KObj::Vector(Attribute::Sorted, KVector::Symbol(vec!["a".into(), "b".into()])),
+            KObj::from(KVector::Long(vec![1, 2])),
+        );
+        let bytes = [&[99][..], &dict.serialize()].concat();
+        assert_eq!(&bytes[..3], &[99, 11, 1]);
+        assert_eq!(KView::decode(&bytes).unwrap().into_kobj(), dict);
+        assert_eq!(format!("{}", dict), "`s#(`a;`b)!(1j;2j)");
+    }
+
+    #[test]
+    fn keyed_table_round_trips() {
+        // ([k:`a`b] v:1 2)
+        let bytes = [99, 98, 0, 99, 11, 0, 1, 0, 0, 0, 107, 0, 0, 0, 1, 0, 0, 0, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0,
+            98, 0, 99, 11, 0, 1, 0, 0, 0, 118, 0, 0, 0, 1, 0, 0, 0, 7, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+        let keyed = KObj::dict(
+            KObj::Table(vec![KObj::Atom(KType::Symbol("k".into()))], vec![KObj::from(KVector::Symbol(vec!["a".into(), "b".into()]))]),
+            KObj::Table(vec![KObj::Atom(KType::Symbol("v".into()))], vec![KObj::from(KVector::Long(vec![1, 2]))]),
+        );
+        assert_eq!(KView::decode(&bytes).unwrap().into_kobj(), keyed);
+        assert_eq!(Decoder::new(&bytes).decode_owned(TextMode::Lossy, None).unwrap(), keyed);
+        assert_eq!(keyed.serialize(), &bytes[1..]);
+        assert_eq!(keyed.encoded_len(), bytes.len());
+    }
+
+    #[test]
+    fn encoded_len_matches_serialize() {
+        let values = vec![
+            KObj::string("hello"),
+            KObj::List(6, vec![KObj::Atom(KType::Int(1)), KObj::Atom(KType::Int(2))]),
+            KObj::List(7, vec![KObj::Atom(KType::Long(1)), KObj::Atom(KType::Symbol("a".into()))]),
+            KObj::GenericList(vec![KObj::GenericList(vec![KObj::Atom(KType::Byte(1))]), KObj::Vector(Attribute::None, KVector::Symbol(vec!["ab".into()]))]),
+            KObj::dict(KObj::from(KVector::Long(vec![1])), KObj::GenericList(vec![KObj::Vector(Attribute::None, KVector::Real(vec![1.]))])),
+            KObj::lambda("{x+1}"),
+            KObj::Error("type".to_string()),
+        ];
+        for v in values {
+            assert_eq!(v.encoded_len(), 1 + v.serialize().len(), "{}", v);
+        }
+    }
+
+    #[test]
+    fn lambda_round_trips() {
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        let lambda = KObj::lambda("{x+1}");
+        kdb.send_async(&lambda).unwrap();
+        let written = kdb.writer.take().unwrap().into_inner().unwrap();
+        assert_eq!(KView::decode(&written[8..]).unwrap().into_kobj(), lambda);
+    }
+}
+
+#[cfg(test)]
+mod view_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+    use crate::{Attribute, VectorView};
+
+    // ([] sym:`a`b; px:1 2f)
+    const TABLE: [u8;62] = [1, 0, 0, 0, 62, 0, 0, 0, 98, 0, 99, 11, 0, 2, 0, 0, 0, 115, 121, 109, 0, 112, 120, 0,
+        0, 0, 2, 0, 0, 0, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0, 9, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 0, 64];
+
+    #[test]
+    fn view_table_borrows_columns() {
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+        let mut buf = vec![];
+        match kdb.read_view(&mut buf).unwrap() {
+            KView::Table(names, columns) => {
+                assert_eq!(names.iter().collect::<Vec<_>>(), vec!["sym", "px"]);
+                match (&columns[0], &columns[1]) {
+                    (KView::Vector(_, VectorView::Symbol(sym)), KView::Vector(_, VectorView::Float(px))) => {
+                        assert_eq!(sym.get(1), Some("b"));
+                        assert_eq!(px.iter().collect::<Vec<_>>(), vec![1., 2.]);
+                        assert_eq!(px.get(2), None);
+                        assert_eq!(px.get(usize::MAX / 8), None);
+                    },
+                    other => panic!("unexpected columns {:?}", other),
+                }
+            },
+            other => panic!("unexpected view {:?}", other),
+        }
+    }
+
+    #[test]
+    fn view_matches_owned_read() {
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+        let owned = kdb.read().unwrap();
+        kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+        let mut buf = vec![];
+        assert_eq!(kdb.read_view(&mut buf).unwrap().into_kobj(), owned);
+    }
+
+    #[test]
+    fn view_symbol_atom() {
+        let mut kdb = setup_kdb();
+        let byte_data = vec![1, 0, 0, 0, 12, 0, 0, 0, 245, 97, 98, 0];
+        kdb.reader = Some(BufReader::new(byte_data.as_slice()));
+        let mut buf = vec![];
+        assert_eq!(kdb.read_view(&mut buf).unwrap(), KView::Symbol("ab"));
+    }
+
+    #[test]
+    fn view_rejects_invalid_utf8() {
+        let mut kdb = setup_kdb();
+        let byte_data = vec![1, 0, 0, 0, 12, 0, 0, 0, 245, 255, 98, 0];
+        kdb.reader = Some(BufReader::new(byte_data.as_slice()));
+        let mut buf = vec![];
+        assert_eq!(kdb.read_view(&mut buf).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_lazy_table() {
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+        match kdb.read_lazy().unwrap() {
+            Lazy::Table(table) => assert_eq!(table.column("px").unwrap().unwrap(), KObj::Vector(Attribute::None, crate::KVector::Float(vec![1., 2.]))),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_reuses_its_buffer() {
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+        kdb.read().unwrap();
+        let ptr = kdb.read_buf.as_ptr();
+        kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+        kdb.read().unwrap();
+        assert_eq!(kdb.read_buf.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn read_buffer_shrinks_after_a_large_message() {
+        // a vector of 2^18 longs, 2 MiB
+        let count = 1 << 18;
+        let len = 8 + 6 + 8 * count;
+        let mut message = vec![1, 0, 0, 0];
+        message.extend_from_slice(&(len as u32).to_le_bytes());
+        message.extend_from_slice(&[7, 0]);
+        message.extend_from_slice(&(count as u32).to_le_bytes());
+        message.resize(len, 0);
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(message.as_slice()));
+        assert_eq!(kdb.read().unwrap(), KObj::from(KVector::Long(vec![0; count])));
+        assert!(kdb.read_buf.capacity() <= READ_BUF_CAP);
+    }
+
+    #[test]
+    fn read_lazy_takes_buffers_from_pool() {
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+        let pool = BufferPool::new(4);
+        let buf = Vec::with_capacity(TABLE.len());
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+        kdb.set_buffer_pool(pool.clone());
+        let body = kdb.read_lazy().unwrap().into_body().unwrap();
+        assert_eq!(body.as_ptr(), ptr);
+        pool.put(body);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[test]
+    fn read_interns_symbols() {
+        let mut kdb = setup_kdb();
+        kdb.set_symbol_interning(true);
+        let mut syms = vec![];
+        for _ in 0..2 {
+            kdb.reader = Some(BufReader::new(TABLE.as_slice()));
+            match kdb.read().unwrap() {
+                KObj::Table(names, _) => syms.push(names[0].clone()),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        match (&syms[0], &syms[1]) {
+            (KObj::Atom(KType::Symbol(a)), KObj::Atom(KType::Symbol(b))) => assert_eq!(a.as_ptr(), b.as_ptr()),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(kdb.symbol_interner().unwrap().len(), 4);
+    }
+}
+
+#[cfg(test)]
+mod temporal_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+
+    #[test]
+    fn temporal_atoms_round_trip() {
+        use crate::temporal::*;
+        let atoms = vec![
+            KType::Timestamp(Timestamp(-1)), KType::Month(Month(-13)), KType::Date(Date(7000)),
+            KType::Datetime(Datetime(-0.123456789)), KType::Timespan(Timespan(123_456_789_012_345)),
+            KType::Minute(Minute(1501)), KType::Second(Second(-5)), KType::Time(Time(86_400_001)),
+        ];
+        for atom in atoms {
+            let mut kdb = setup_kdb();
+            kdb.writer = Some(BufWriter::new(vec![]));
+            kdb.send_async(&KObj::Atom(atom.clone())).unwrap();
+            let written = kdb.writer.take().unwrap().into_inner().unwrap();
+            assert_eq!(written.len(), 9 + atom.serialized_len());
+            assert_eq!(KView::decode(&written[8..]).unwrap().into_kobj(), KObj::Atom(atom));
+        }
+    }
+}
+
+#[cfg(test)]
+mod null_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+
+    #[test]
+    fn read_nulls_and_infinities() {
+        let messages: Vec<(&'static [u8], &str)> = vec![
+            (&[1, 0, 0, 0, 17, 0, 0, 0, 249, 0, 0, 0, 0, 0, 0, 0, 128], "0Nj"),
+            (&[1, 0, 0, 0, 17, 0, 0, 0, 249, 1, 0, 0, 0, 0, 0, 0, 128], "-0Wj"),
+            (&[1, 0, 0, 0, 13, 0, 0, 0, 250, 255, 255, 255, 127], "0Wi"),
+            (&[1, 0, 0, 0, 17, 0, 0, 0, 247, 0, 0, 0, 0, 0, 0, 248, 255], "0n"),
+            (&[1, 0, 0, 0, 13, 0, 0, 0, 242, 0, 0, 0, 128], "0Nd"),
+            (&[1, 0, 0, 0, 25, 0, 0, 0, 254, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "0Ng"),
+        ];
+        for (bytes, shown) in messages {
+            let mut kdb = setup_kdb();
+            kdb.reader = Some(BufReader::new(bytes));
+            let data = kdb.read().unwrap();
+            assert_eq!(format!("{}", data), shown);
+            match data {
+                KObj::Atom(k) => {
+                    assert!(k.is_null() != k.is_infinite());
+                    if k.is_null() {
+                        assert_eq!((k.as_long(), k.as_int(), k.as_float(), k.as_date(), k.as_guid()), (None, None, None, None, None));
+                    };
+                },
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn null_long_round_trips() {
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        kdb.send_async(&KObj::Atom(KType::Long(crate::null::LONG))).unwrap();
+        assert_eq!(kdb.writer.unwrap().get_ref(), &vec![1, 0, 0, 0, 17, 0, 0, 0, 249, 0, 0, 0, 0, 0, 0, 0, 128]);
+    }
+}
+
+#[cfg(test)]
+mod text_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+    use crate::Symbol;
+
+    #[test]
+    fn read_text_that_is_not_utf8() {
+        // (`caf\xe9;"\xe9") in Latin-1
+        let message = [1, 0, 0, 0, 22, 0, 0, 0, 0, 0, 2, 0, 0, 0, 245, 99, 97, 102, 233, 0, 246, 233];
+        let byte_data = message.repeat(3);
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(byte_data.as_slice()));
+
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::InvalidData);
+
+        kdb.set_text_mode(TextMode::Lossy);
+        assert_eq!(kdb.read().unwrap(), KObj::GenericList(vec![
+            KObj::Atom(KType::Symbol("caf\u{fffd}".into())),
+            KObj::Atom(KType::Char('\u{fffd}')),
+        ]));
+
+        kdb.set_text_mode(TextMode::Bytes);
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::GenericList(vec![
+            KObj::Atom(KType::Symbol(Symbol::from_bytes(b"caf\xe9"))),
+            KObj::Atom(KType::Char('\u{e9}')),
+        ]));
+        assert_eq!(data.serialize(), &message[9..]);
+    }
+
+    #[test]
+    fn refuses_chars_wider_than_a_byte() {
+        let mut kdb = setup_kdb();
+        kdb.writer = Some(BufWriter::new(vec![]));
+        let euro = KObj::list(vec![KObj::Atom(KType::Char('a')), KObj::Atom(KType::Char('\u{20ac}'))]);
+        assert_eq!(kdb.send_async(&euro).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert!(kdb.writer.as_ref().unwrap().get_ref().is_empty());
+        assert_ne!(kdb.state(), ConnectionState::Failed);
+        assert_eq!(KType::Char('\u{20ac}').serialize_into(&mut vec![]).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(KType::Char('\u{e9}').serialize(), vec![0xe9]);
+    }
+}
+
+#[cfg(test)]
+mod enum_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+
+    #[test]
+    fn resolve_enum() {
+        // `sym$`b`a`b
+        let enumeration = Enumeration { code: 20, domain: "sym".into(), indices: vec![1, 0, 1] };
+        let symbols = vec!["a".into(), "b".into()];
+        assert_eq!(enumeration.resolve(&symbols).unwrap(), KObj::from(KVector::Symbol(vec!["b".into(), "a".into(), "b".into()])));
+        let bad = Enumeration { code: -20, domain: "sym".into(), indices: vec![2] };
+        assert_eq!(bad.resolve(&symbols).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        // the server answers "sym" with `a`b
+        let response = vec![1, 2, 0, 0, 18, 0, 0, 0, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0];
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(response.as_slice()));
+        kdb.writer = Some(BufWriter::new(vec![]));
+        assert_eq!(kdb.resolve_enum(&enumeration).unwrap(), enumeration.resolve(&symbols).unwrap());
+        assert_eq!(&kdb.writer.unwrap().get_ref()[8..], &[10, 0, 3, 0, 0, 0, 115, 121, 109]);
+    }
+}
+
+#[cfg(test)]
+mod unsupported_tests {
+    use super::*;
+    use super::test_util::setup_kdb;
+
+    #[test]
+    fn read_types_rsq_cannot() {
+        // (1j;foreign), then a foreign on its own
+        let nested = [1, 0, 0, 0, 26, 0, 0, 0, 0, 0, 2, 0, 0, 0, 249, 1, 0, 0, 0, 0, 0, 0, 0, 112, 222, 173];
+        let foreign = [1, 0, 0, 0, 12, 0, 0, 0, 112, 1, 2, 3];
+        let byte_data = [nested.as_slice(), &foreign, &nested, &foreign].concat();
+        let mut kdb = setup_kdb();
+        kdb.reader = Some(BufReader::new(byte_data.as_slice()));
+
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_eq!(kdb.read().unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_ne!(kdb.state(), ConnectionState::Failed);
+        assert_eq!(KView::decode(&nested[8..]).unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_eq!(KView::decode(&foreign[8..]).unwrap_err().kind(), ErrorKind::Unsupported);
+
+        kdb.set_lenient(true);
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::Opaque { type_code: 0, bytes: nested[9..].to_vec() });
+        assert_eq!(data.serialize(), &nested[9..]);
+        let data = kdb.read().unwrap();
+        assert_eq!(data, KObj::Opaque { type_code: 112, bytes: vec![1, 2, 3] });
+        kdb.writer = Some(BufWriter::new(vec![]));
+        kdb.send_async(&data).unwrap();
+        assert_eq!(&kdb.writer.unwrap().get_ref()[4..], &foreign[4..]);
+    }
+}
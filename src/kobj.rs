@@ -1,27 +1,58 @@
-use super::ktype::KType;
+use super::adverb::Adverb;
+use super::attribute::Attribute;
+use super::enumeration::Enumeration;
+use super::ktype::{KType, char_byte};
+use super::kvector::KVector;
+use super::null;
+use super::symbol::Symbol;
 use std::fmt;
+use std::io::{self, Error, ErrorKind, Write};
 use uuid::Uuid;
-use chrono::Utc;
+use super::temporal::{Timestamp, Month, Date, Datetime, Timespan, Minute, Second, Time};
 use byteorder::{LittleEndian, WriteBytesExt};
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[derive(PartialEq)]
 pub enum KObj {
     Atom(KType),
-    List(Vec<KObj>),
+    /// Atoms sent as a simple list of type `code`, e.g. 7 for longs, so an
+    /// empty one still has a type, like `` `long$() ``. Elements that don't
+    /// match the type are sent as a general list instead.
+    List(i8, Vec<KObj>),
+    Vector(Attribute, KVector),
+    Enum(Attribute, Enumeration),
     GenericList(Vec<KObj>),
-    Dict(Vec<KObj>, Vec<KObj>),
+    /// Keys and values, each a list or a table, e.g. `` `a`b!1 2 `` or a
+    /// keyed table.
+    Dict(Box<KObj>, Box<KObj>),
     Table(Vec<KObj>, Vec<KObj>),
     Error(String),
-    Lambda(String)
+    /// A function, as its source, e.g. `{x+1}`, and the namespace it was
+    /// defined in, which is empty for the root namespace.
+    Lambda { context: Symbol, body: String },
+    /// A function with some of its arguments fixed, e.g. `{x+y}[1]`, as the
+    /// function followed by its arguments.
+    Projection(Vec<KObj>),
+    /// Functions composed into one, e.g. `'[neg;sum]`.
+    Composition(Vec<KObj>),
+    /// A function derived with an adverb, e.g. `+/`.
+    Adverb(Adverb, Box<KObj>),
+    /// A message rsq can't read, kept as its type and the bytes after it so
+    /// it can be sent on unchanged. Only `Kdb::read` in lenient mode makes
+    /// these, see `Kdb::set_lenient`.
+    Opaque { type_code: i8, bytes: Vec<u8> },
 }
 
 impl fmt::Display for KObj {
     fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
         match self {
             KObj::Atom(k) => k.fmt(f),
-            KObj::List(k) => {
+            KObj::List(code, k) if k.is_empty() => match KVector::new(*code) {
+                Some(v) => v.fmt(f),
+                None => write!(f, "()"),
+            },
+            KObj::List(_, k) => {
                 let list: Vec<String> = k.iter().map(|x|format!("{}", x)).collect();
                 let needs_enlist = if 1 == list.len(){
                     String::from("enlist ")
@@ -31,6 +62,8 @@ impl fmt::Display for KObj {
                 let string_list = String::from("(") + &needs_enlist + &list.join(";") + ")";
                 write!(f, "{}", string_list)
             },
+            KObj::Vector(a, k) => write!(f, "{}{}", a, k),
+            KObj::Enum(a, e) => write!(f, "{}{}", a, e),
             KObj::GenericList(k) => {
                 let list: Vec<String> = k.iter().map(|x|format!("{}", x)).collect();
                 let needs_enlist = if 1 == list.len(){
@@ -41,13 +74,10 @@ impl fmt::Display for KObj {
                 let string_list = String::from("(") + &needs_enlist + &list.join(";") + ")";
                 write!(f, "{}", string_list)
             },
-            KObj::Dict(k,v) => {
-                let keys: Vec<String> = k.iter().map(|x|format!("{}", x)).collect();
-                let keys = String::from("(") + &keys.join(";") + ")";
-                write!(f, "{}!", keys).ok();
-                let vals: Vec<String> = v.iter().map(|x|format!("{}", x)).collect();
-                let vals = String::from("(") + &vals.join(";") + ")";
-                write!(f, "{}", vals)
+            KObj::Dict(k,v) => match (&**k, &**v) {
+                // a keyed table, whose sides need brackets to be read back
+                (KObj::Table(_,_), _) => write!(f, "({})!({})", k, v),
+                _ => write!(f, "{}!{}", k, v),
             },
             KObj::Table(k,v) => {
                 let keys: Vec<String> = k.iter().map(|x|format!("{}", x)).collect();
@@ -57,12 +87,27 @@ impl fmt::Display for KObj {
                 let vals = String::from("(") + &vals.join(";") + ")";
                 write!(f, "{}", vals)
             },
-            KObj::Lambda(l) => {
-                write!(f, "{}", l)
+            KObj::Lambda { body, .. } => {
+                write!(f, "{}", body)
             }
             KObj::Error(e) => {
                 write!(f, "'{}", e)
             }
+            KObj::Projection(p) => match (self.internal_function(), p.split_first()) {
+                (Some((null::LONG, _)), _) => write!(f, "0N!"),
+                (Some((code, _)), _) => write!(f, "{}!", code),
+                (None, Some((func, args))) => {
+                    let args: Vec<String> = args.iter().map(|x|format!("{}", x)).collect();
+                    write!(f, "{}[{}]", func, args.join(";"))
+                },
+                (None, None) => write!(f, "()"),
+            },
+            KObj::Composition(c) => {
+                let funcs: Vec<String> = c.iter().map(|x|format!("{}", x)).collect();
+                write!(f, "'[{}]", funcs.join(";"))
+            },
+            KObj::Adverb(a, func) => write!(f, "{}{}", func, a),
+            KObj::Opaque { type_code, bytes } => write!(f, "<type {}, {} bytes>", type_code, bytes.len()),
         }
     }
 }
@@ -72,110 +117,360 @@ impl KObj {
 
     pub fn new(code: i8) -> KObj {
         match code {
-            code if (code > 0 && code <= 19) && (code != 10) => KObj::List(vec![]),
-             00 => KObj::GenericList(vec![]),
-            -01 => KObj::Atom(KType::Boolean(false)),
-            -02 => KObj::Atom(KType::Guid(Uuid::nil())),
-            -04 => KObj::Atom(KType::Byte(0)),
-            -05 => KObj::Atom(KType::Short(0)),
-            -06 => KObj::Atom(KType::Int(0)),
-            -07 => KObj::Atom(KType::Long(0)),
-            -08 => KObj::Atom(KType::Real(0.)),
-            -09 => KObj::Atom(KType::Float(0.)),
+            code if code > 0 && code <= 19 => match KVector::new(code) {
+                Some(v) => KObj::Vector(Attribute::None, v),
+                None => KObj::Error(String::from("")),
+            },
+              0 => KObj::GenericList(vec![]),
+            code if matches!(code, 20..=76 | -76..=-20) => KObj::Enum(Attribute::None, Enumeration {
+                code,
+                domain: Symbol::from(""),
+                indices: vec![],
+            }),
+             -1 => KObj::Atom(KType::Boolean(false)),
+             -2 => KObj::Atom(KType::Guid(Uuid::nil())),
+             -4 => KObj::Atom(KType::Byte(0)),
+             -5 => KObj::Atom(KType::Short(0)),
+             -6 => KObj::Atom(KType::Int(0)),
+             -7 => KObj::Atom(KType::Long(0)),
+             -8 => KObj::Atom(KType::Real(0.)),
+             -9 => KObj::Atom(KType::Float(0.)),
             -10 => KObj::Atom(KType::Char(' ')),
-             10 => KObj::Atom(KType::String(String::from(""))),
-            -11 => KObj::Atom(KType::Symbol(String::from(""))),
-            -12 => KObj::Atom(KType::Timestamp(Utc::now())),
-            -13 => KObj::Atom(KType::Month(Utc::today())),
-            -14 => KObj::Atom(KType::Date(Utc::today())),
-            -15 => KObj::Atom(KType::Datetime(Utc::now())),
-            -16 => KObj::Atom(KType::Timespan(Utc::now())),
-            -17 => KObj::Atom(KType::Minute(Utc::now())),
-            -18 => KObj::Atom(KType::Second(Utc::now())),
-            -19 => KObj::Atom(KType::Time(Utc::now())),
-             99 => KObj::Dict(vec![], vec![]),
+            -11 => KObj::Atom(KType::Symbol(Symbol::from(""))),
+            -12 => KObj::Atom(KType::Timestamp(Timestamp(0))),
+            -13 => KObj::Atom(KType::Month(Month(0))),
+            -14 => KObj::Atom(KType::Date(Date(0))),
+            -15 => KObj::Atom(KType::Datetime(Datetime(0.))),
+            -16 => KObj::Atom(KType::Timespan(Timespan(0))),
+            -17 => KObj::Atom(KType::Minute(Minute(0))),
+            -18 => KObj::Atom(KType::Second(Second(0))),
+            -19 => KObj::Atom(KType::Time(Time(0))),
+             99 => KObj::dict(KObj::GenericList(vec![]), KObj::GenericList(vec![])),
              98 => KObj::Table(vec![], vec![]),
-            100 => KObj::Lambda(String::from("")),
+            100 => KObj::lambda(""),
+            104 => KObj::Projection(vec![]),
+            105 => KObj::Composition(vec![]),
+            code @ 106..=111 => KObj::Adverb(Adverb::from_code(code).unwrap(), Box::new(KObj::GenericList(vec![]))),
             101 => KObj::Atom(KType::Unary(0)),
             102 => KObj::Atom(KType::Operator(0)),
+            103 => KObj::Atom(KType::Iterator(0)),
            -128 => KObj::Error(String::from("")),
               _ => KObj::Error(String::from(""))
         }
     }
 
+    /// A char vector holding `s`, which is how q represents strings.
+    pub fn string(s: &str) -> KObj {
+        KObj::Vector(Attribute::None, KVector::from(s))
+    }
+
+    /// A function defined by `body`, e.g. `{x+1}`, in the root namespace.
+    pub fn lambda(body: &str) -> KObj {
+        KObj::lambda_in("", body)
+    }
+
+    /// A function defined by `body` in the namespace `context`, so that q
+    /// resolves its globals there.
+    pub fn lambda_in(context: &str, body: &str) -> KObj {
+        KObj::Lambda { context: Symbol::from(context), body: body.to_string() }
+    }
+
+    /// The code and name of an internal function, e.g. `(-8, "to bytes")` for
+    /// `-8!`, which q sends as a projection of `!` on its code.
+    pub fn internal_function(&self) -> Option<(i64, &'static str)> {
+        let code = match self {
+            KObj::Projection(p) => match p.as_slice() {
+                [KObj::Atom(KType::Operator(16)), KObj::Atom(KType::Long(n))] => *n,
+                [KObj::Atom(KType::Operator(16)), KObj::Atom(KType::Int(null::INT))] => null::LONG,
+                [KObj::Atom(KType::Operator(16)), KObj::Atom(KType::Int(n))] => *n as i64,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        KType::internal_mapping(code).map(|name| (code, name))
+    }
+
+    /// The text of a char vector, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KObj::Vector(_, v) => v.as_str(),
+            _ => None,
+        }
+    }
+
+    /// A dictionary from `keys` to `values`, which should be lists, or tables
+    /// for a keyed table, of the same length.
+    pub fn dict(keys: KObj, values: KObj) -> KObj {
+        KObj::Dict(Box::new(keys), Box::new(values))
+    }
+
+    /// A list of `items`, sent as a simple list if they are all atoms of one
+    /// type and as a general list otherwise.
+    pub fn list(items: Vec<KObj>) -> KObj {
+        match simple_list_type(&items) {
+            Some(code) => KObj::List(code as i8, items),
+            None => KObj::GenericList(items),
+        }
+    }
+
+    /// A simple list of type `code`, e.g. 7 for longs. Fails with
+    /// `ErrorKind::InvalidInput` unless every item is an atom of that type.
+    pub fn typed_list(code: i8, items: Vec<KObj>) -> Result<KObj, Error> {
+        if !is_list_type(code) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{}h is not a simple list type", code)))
+        };
+        if let Some(item) = items.iter().find(|i| i.type_as_bytes() as i8 != -code) {
+            let msg = format!("expected atoms of type {}h, found {}h", -code, item.type_as_bytes() as i8);
+            return Err(Error::new(ErrorKind::InvalidInput, msg))
+        };
+        Ok(KObj::List(code, items))
+    }
+
+    /// Panics on a char above U+00FF, which `serialize_into` and the `send`
+    /// methods report as `ErrorKind::InvalidInput`.
     pub fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.serialized_len());
+        self.serialize_into(&mut result).unwrap();
+        result
+    }
+
+    // find anything serialize_into would fail on, before any of a message
+    // has been written
+    pub(crate) fn check_encodable(&self) -> Result<(), Error> {
         match self {
-            KObj::Atom(t) => t.serialize(),
-            KObj::List(t) => {
-                let mut result = vec![];
-                // 1 byte for attribute
-                result.push(0);
+            KObj::Atom(KType::Char(c)) => char_byte(*c).map(|_| ()),
+            KObj::List(_, t) | KObj::GenericList(t) | KObj::Table(_, t)
+            | KObj::Projection(t) | KObj::Composition(t) => t.iter().try_for_each(KObj::check_encodable),
+            KObj::Dict(k, v) => k.check_encodable().and_then(|()| v.check_encodable()),
+            KObj::Adverb(_, func) => func.check_encodable(),
+            _ => Ok(()),
+        }
+    }
 
-                // 4 bytes for length
-                let mut length_buf = vec![]; 
-                length_buf.write_i32::<LittleEndian>(t.len() as i32).unwrap();
-                for b in length_buf.iter(){
-                    result.push(*b);
-                };
-                // ? bytes for data
-                for k in t.iter() {
-                    for byte in k.serialize(){
-                        result.push(byte);
-                    };
-                };
-                result
-            },
-            KObj::GenericList(t) => {
-                let mut result = vec![];
+    /// Write the same bytes as `serialize` straight into `w`, without
+    /// building any intermediate buffers.
+    pub fn serialize_into<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            KObj::Atom(t) => t.serialize_into(w),
+            KObj::List(code, t) if is_uniform(*code, t) => {
                 // 1 byte for attribute
-                result.push(0);
-
+                w.write_u8(0)?;
                 // 4 bytes for length
-                let mut length_buf = vec![]; 
-                length_buf.write_i32::<LittleEndian>(t.len() as i32).unwrap();
-                for b in length_buf.iter(){
-                    result.push(*b);
-                };
+                w.write_i32::<LittleEndian>(t.len() as i32)?;
                 // ? bytes for data
-                for k in t.iter() {
-                    result.push(k.type_as_bytes());
-                    for byte in k.serialize(){
-                        result.push(byte);
-                    };
-                };
-                result
+                t.iter().try_for_each(|k| k.serialize_into(w))
+            },
+            KObj::List(_, t) => write_generic_list(t, w),
+            KObj::Vector(a, t) => t.serialize_with(*a, w),
+            KObj::Enum(a, e) => e.serialize_with(*a, w),
+            KObj::GenericList(t) => write_generic_list(t, w),
+            KObj::Dict(k,v) => {
+                w.write_u8(k.type_as_bytes())?;
+                k.serialize_into(w)?;
+                w.write_u8(v.type_as_bytes())?;
+                v.serialize_into(w)
+            },
+            KObj::Table(k,v) => {
+                // attribute, then a dictionary of column names to columns
+                w.write_all(&[0, 99])?;
+                write_column_names(k, w)?;
+                w.write_all(&[0, 0])?;
+                w.write_i32::<LittleEndian>(v.len() as i32)?;
+                v.iter().try_for_each(|c| {
+                    w.write_u8(c.type_as_bytes())?;
+                    c.serialize_into(w)
+                })
             },
-            KObj::Dict(_,_) => vec![],
-            KObj::Table(_,_) => vec![],
-            KObj::Lambda(_) => vec![],
-            KObj::Error(_) => vec![]
+            KObj::Lambda { context, body } => {
+                // the context, then the body as a char vector
+                w.write_all(context.as_bytes())?;
+                w.write_all(&[0, 10, 0])?;
+                w.write_i32::<LittleEndian>(body.len() as i32)?;
+                w.write_all(body.as_bytes())
+            },
+            KObj::Error(e) => {w.write_all(e.as_bytes())?; w.write_u8(0)},
+            // like a general list, but with no attribute
+            KObj::Projection(t) | KObj::Composition(t) => {
+                w.write_i32::<LittleEndian>(t.len() as i32)?;
+                t.iter().try_for_each(|k| {
+                    w.write_u8(k.type_as_bytes())?;
+                    k.serialize_into(w)
+                })
+            },
+            KObj::Adverb(_, func) => {
+                w.write_u8(func.type_as_bytes())?;
+                func.serialize_into(w)
+            },
+            KObj::Opaque { bytes, .. } => w.write_all(bytes),
         }
     }
 
+    /// The number of bytes `serialize` produces, worked out without encoding.
+    pub fn serialized_len(&self) -> usize {
+        match self {
+            KObj::Atom(t) => t.serialized_len(),
+            KObj::List(code, t) if is_uniform(*code, t) => 5 + t.iter().map(KObj::serialized_len).sum::<usize>(),
+            KObj::List(_, t) => 5 + t.iter().map(KObj::encoded_len).sum::<usize>(),
+            KObj::Vector(_, t) => t.serialized_len(),
+            KObj::Enum(_, e) => e.serialized_len(),
+            KObj::GenericList(t) => 5 + t.iter().map(KObj::encoded_len).sum::<usize>(),
+            KObj::Dict(k,v) => k.encoded_len() + v.encoded_len(),
+            KObj::Table(k,v) => 2 + list_len(k) + 6 + v.iter().map(KObj::encoded_len).sum::<usize>(),
+            KObj::Lambda { context, body } => context.as_bytes().len() + 7 + body.len(),
+            KObj::Error(e) => e.len() + 1,
+            KObj::Projection(t) | KObj::Composition(t) => 4 + t.iter().map(KObj::encoded_len).sum::<usize>(),
+            KObj::Adverb(_, func) => func.encoded_len(),
+            KObj::Opaque { bytes, .. } => bytes.len(),
+        }
+    }
+
+    /// The exact size of this value on the wire, type byte included, i.e.
+    /// a message carrying it is 8 bytes of header longer than this.
+    pub fn encoded_len(&self) -> usize {
+        1 + self.serialized_len()
+    }
+
     pub fn type_as_bytes(&self) -> u8 {
-        let code = match self {
+        match self {
             KObj::Atom(t) => t.type_as_code() as u8,
-            // todo: support generic lists
-            // assumes all lists are of the type of the first element
-            KObj::List(t) => {
-                match &t[0] {
-                    KObj::Atom(t) => (-1 * t.type_as_code()) as u8,
-                    _ => 0u8,
-                }
-            },
+            KObj::List(code, t) if is_uniform(*code, t) => *code as u8,
+            KObj::List(_, _) => 0u8,
+            KObj::Vector(_, t) => t.type_as_code() as u8,
+            KObj::Enum(_, e) => e.code as u8,
             KObj::GenericList(_) => 0u8,
             KObj::Dict(_,_) => 99u8,
             KObj::Table(_,_) => 98u8,
-            KObj::Lambda(_) => 100u8,
-            KObj::Error(_) =>  0u8
-        };
-        code as u8
+            KObj::Lambda { .. } => 100u8,
+            KObj::Error(_) => -128i8 as u8,
+            KObj::Projection(_) => 104u8,
+            KObj::Composition(_) => 105u8,
+            KObj::Adverb(a, _) => a.code() as u8,
+            KObj::Opaque { type_code, .. } => *type_code as u8,
+        }
     }
 
-    pub fn deserialize(&self, data: &Vec<u8>) -> KObj{
+    pub fn deserialize(&self, data: &[u8]) -> KObj{
         match self {
             KObj::Atom(t) => KObj::Atom(t.deserialize(data)),
-            _             => KObj::List(vec![]),  // this will never get used
+            _             => KObj::GenericList(vec![]),  // this will never get used
+        }
+    }
+}
+
+// the simple list type of `items` if they are all atoms of one type
+fn simple_list_type(items: &[KObj]) -> Option<u8> {
+    let code = match items.first() {
+        Some(KObj::Atom(k)) if k.type_as_code() < 0 => k.type_as_code(),
+        _ => return None,
+    };
+    let same = items.iter().all(|i| matches!(i, KObj::Atom(k) if k.type_as_code() == code));
+    if same { Some(-code as u8) } else { None }
+}
+
+fn is_list_type(code: i8) -> bool {
+    (1..=19).contains(&code) && code != 3
+}
+
+// whether a `List` can go out as a simple list of its type
+fn is_uniform(code: i8, items: &[KObj]) -> bool {
+    is_list_type(code) && items.iter().all(|i| matches!(i, KObj::Atom(k) if k.type_as_code() == -code))
+}
+
+fn write_generic_list<W: Write>(items: &[KObj], w: &mut W) -> io::Result<()> {
+    // 1 byte for attribute
+    w.write_u8(0)?;
+    // 4 bytes for length
+    w.write_i32::<LittleEndian>(items.len() as i32)?;
+    // ? bytes for data, each element with its own type
+    items.iter().try_for_each(|k| {
+        w.write_u8(k.type_as_bytes())?;
+        k.serialize_into(w)
+    })
+}
+
+// a table's column names, which q expects as a symbol list even when empty
+fn write_column_names<W: Write>(items: &[KObj], w: &mut W) -> io::Result<()> {
+    let simple = if items.is_empty() { Some(11) } else { simple_list_type(items) };
+    w.write_all(&[simple.unwrap_or(0), 0])?;
+    w.write_i32::<LittleEndian>(items.len() as i32)?;
+    items.iter().try_for_each(|i| {
+        if simple.is_none() {
+            w.write_u8(i.type_as_bytes())?;
+        };
+        i.serialize_into(w)
+    })
+}
+
+fn list_len(items: &[KObj]) -> usize {
+    let data: usize = match simple_list_type(items) {
+        Some(_) => items.iter().map(KObj::serialized_len).sum(),
+        None => items.iter().map(KObj::encoded_len).sum(),
+    };
+    6 + data
+}
+
+impl From<KVector> for KObj {
+    fn from(v: KVector) -> KObj { KObj::Vector(Attribute::None, v) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::Decoder;
+    use crate::{KView, TextMode};
+
+    #[test]
+    fn lambda_keeps_its_context() {
+        // {x} defined in .u
+        let data = [100, 117, 0, 10, 0, 3, 0, 0, 0, 123, 120, 125];
+        let lambda = KView::decode(&data).unwrap().into_kobj();
+        assert_eq!(lambda, KObj::lambda_in("u", "{x}"));
+        assert_eq!(lambda.serialize(), &data[1..]);
+        assert_eq!(lambda.encoded_len(), data.len());
+        assert_eq!(Decoder::new(&data).decode_owned(TextMode::Lossy, None).unwrap(), lambda);
+    }
+
+    #[test]
+    fn display_every_primitive() {
+        let unary = ["::", "+:", "-:", "*:", "%:", "&:", "|:", "^:", "=:", "<:", ">:", "$:", ",:", "#:", "_:", "~:",
+            "!:", "?:", "@:", ".:", "0::", "1::", "2::", "avg", "last", "sum", "prd", "min", "max", "exit", "getenv",
+            "abs", "sqrt", "log", "exp", "sin", "asin", "cos", "acos", "tan", "atan", "enlist", "var", "dev", "hopen"];
+        for (code, name) in unary.iter().enumerate() {
+            assert_eq!(format!("{}", KObj::Atom(KType::Unary(code as u8))), *name);
+        }
+        assert_eq!(format!("{}", KObj::Atom(KType::Unary(unary.len() as u8))), "<unary 45>");
+        let binary = [":", "+", "-", "*", "%", "&", "|", "^", "=", "<", ">", "$", ",", "#", "_", "~", "!", "?", "@",
+            ".", "0:", "1:", "2:", "in", "within", "like", "bin", "ss", "insert", "wsum", "wavg", "div", "xexp",
+            "setenv", "binr", "cov", "cor"];
+        for (code, name) in binary.iter().enumerate() {
+            assert_eq!(format!("{}", KObj::Atom(KType::Operator(code as u8))), *name);
         }
+        assert_eq!(format!("{}", KObj::Atom(KType::Operator(binary.len() as u8))), "<operator 37>");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn display_primitives() {
+        assert_eq!(format!("{}", KObj::Atom(KType::Unary(44))), "hopen");
+        assert_eq!(format!("{}", KObj::Atom(KType::Unary(200))), "<unary 200>");
+        assert_eq!(format!("{}", KObj::Atom(KType::Operator(99))), "<operator 99>");
+        assert_eq!(format!("{}", KObj::Atom(KType::Iterator(4))), "/:");
+        // {x+y}[;1]
+        let projection = KObj::Projection(vec![KObj::lambda("{x+y}"), KObj::Atom(KType::Unary(255)), KObj::Atom(KType::Long(1))]);
+        assert_eq!(format!("{}", projection), "{x+y}[;1j]");
+        let internal = |n| KObj::Projection(vec![KObj::Atom(KType::Operator(16)), KObj::Atom(KType::Long(n))]);
+        assert_eq!(format!("{}", internal(-8)), "-8!");
+        assert_eq!(internal(-8).internal_function(), Some((-8, "to bytes")));
+        assert_eq!(internal(-29).internal_function(), Some((-29, ".j.k")));
+        assert_eq!(format!("{}", internal(i64::MIN)), "0N!");
+        assert_eq!(internal(i64::MIN).internal_function(), Some((i64::MIN, "show")));
+        // not an internal function, just ! projected on a number
+        assert_eq!(internal(-99).internal_function(), None);
+        assert_eq!(format!("{}", internal(-99)), "![-99j]");
+        // -1!, hsym, as q sends it
+        let hsym = KView::decode(&[104, 2, 0, 0, 0, 102, 16, 249, 255, 255, 255, 255, 255, 255, 255, 255]).unwrap().into_kobj();
+        assert_eq!(hsym.internal_function(), Some((-1, "hsym")));
+        assert_eq!(format!("{}", hsym), "-1!");
+        // (/), an iterator on its own
+        assert_eq!(KView::decode(&[103, 1]).unwrap().into_kobj(), KObj::Atom(KType::Iterator(1)));
+    }
+}
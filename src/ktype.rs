@@ -1,9 +1,14 @@
 use std::fmt;
+use std::convert::TryFrom;
+use std::io::{self, Error, ErrorKind, Write};
 use uuid::Uuid;
-use chrono::{Date, DateTime, Utc, NaiveDateTime, NaiveDate, Datelike, Timelike};
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use super::adverb::Adverb;
+use super::null;
+use super::symbol::Symbol;
+use super::temporal::{Timestamp, Month, Date, Datetime, Timespan, Minute, Second, Time};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[derive(PartialEq)]
 pub enum KType {
     Boolean(bool),
@@ -15,50 +20,203 @@ pub enum KType {
     Real(f32),
     Float(f64),
     Char(char),
-    String(String),
-    Symbol(String),
-    Timestamp(DateTime<Utc>),
-    Month(Date<Utc>),
-    Date(Date<Utc>),
-    Datetime(DateTime<Utc>),
-    Timespan(DateTime<Utc>),
-    Minute(DateTime<Utc>),
-    Second(DateTime<Utc>),
-    Time(DateTime<Utc>),
+    Symbol(Symbol),
+    Timestamp(Timestamp),
+    Month(Month),
+    Date(Date),
+    Datetime(Datetime),
+    Timespan(Timespan),
+    Minute(Minute),
+    Second(Second),
+    Time(Time),
     Unary(u8),
     Operator(u8),
+    Iterator(u8),
+}
+
+// a char goes out as a single byte, so only U+0000 to U+00FF can be sent
+pub(crate) fn char_byte(c: char) -> Result<u8, Error> {
+    u8::try_from(u32::from(c))
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("char {:?} doesn't fit in a byte", c)))
 }
 
 impl fmt::Display for KType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             KType::Boolean(k)   => {write!(f, "{}b", if *k {1} else {0})},
+            KType::Guid(k) if k.is_nil() => write!(f, "0Ng"),
             KType::Guid(k)      => write!(f, "\"G\"$\"{}\"",k),
             KType::Byte(k)      => write!(f, "{}",k),
-            KType::Short(k)     => write!(f, "{}h",k),
-            KType::Int(k)       => write!(f, "{}i",k),
-            KType::Long(k)      => write!(f, "{}j",k),
+            KType::Short(k)     => match *k {
+                null::SHORT => write!(f, "0Nh"),
+                null::SHORT_INF => write!(f, "0Wh"),
+                k if k == -null::SHORT_INF => write!(f, "-0Wh"),
+                k => write!(f, "{}h",k),
+            },
+            KType::Int(k)       => match *k {
+                null::INT => write!(f, "0Ni"),
+                null::INT_INF => write!(f, "0Wi"),
+                k if k == -null::INT_INF => write!(f, "-0Wi"),
+                k => write!(f, "{}i",k),
+            },
+            KType::Long(k)      => match *k {
+                null::LONG => write!(f, "0Nj"),
+                null::LONG_INF => write!(f, "0Wj"),
+                k if k == -null::LONG_INF => write!(f, "-0Wj"),
+                k => write!(f, "{}j",k),
+            },
+            KType::Real(k) if k.is_nan() => write!(f, "0Ne"),
+            KType::Real(k) if k.is_infinite() => write!(f, "{}0We", if *k < 0. {"-"} else {""}),
             KType::Real(k)      => write!(f, "{}e",k),
+            KType::Float(k) if k.is_nan() => write!(f, "0n"),
+            KType::Float(k) if k.is_infinite() => write!(f, "{}0w", if *k < 0. {"-"} else {""}),
             KType::Float(k)     => write!(f, "{}f",k),
             KType::Char(k)      => write!(f, "\"{}\"",k),
-            KType::String(k)    => write!(f, "\"{}\"",k),
             KType::Symbol(k)    => write!(f, "`{}",k),
-            KType::Timestamp(k) => write!(f, "{}", k.format("%Y.%m.%dD%H:%M:%S.%f")),
-            KType::Month(k)     => write!(f, "{}", k.format("%Y.%mm")),
-            KType::Date(k)      => write!(f, "{}", k.format("%Y.%m.%d")),
-            KType::Datetime(k)  => write!(f, "{}", k.format("%Y.%m.%dT%H:%M:%S%.3f")),
-            //todo: fix this to handle date offsets
-            KType::Timespan(k)  => write!(f, "{}", k.format("0D%H:%M:%S.%f")),
-            KType::Minute(k)    => write!(f, "{}", k.format("%H:%M")),
-            KType::Second(k)    => write!(f, "{}", k.format("%H:%M:%S")),
-            KType::Time(k)      => write!(f, "{}", k.format("%H:%M:%S%.3f")),
+            KType::Timestamp(k) => k.fmt(f),
+            KType::Month(k)     => k.fmt(f),
+            KType::Date(k)      => k.fmt(f),
+            KType::Datetime(k)  => k.fmt(f),
+            KType::Timespan(k)  => k.fmt(f),
+            KType::Minute(k)    => k.fmt(f),
+            KType::Second(k)    => k.fmt(f),
+            KType::Time(k)      => k.fmt(f),
             KType::Unary(k)     => write!(f, "{}", KType::unary_mapping(k)),
             KType::Operator(k)  => write!(f, "{}", KType::operator_mapping(k)),
+            KType::Iterator(k)  => match Adverb::from_code(k.wrapping_add(106) as i8) {
+                Some(a) => a.fmt(f),
+                _ => write!(f, "<iterator {}>", k),
+            },
         }
     }
 }
 
 impl KType {
+    /// Whether this is one of kdb+'s in-band nulls, e.g. `0N` or `` ` ``.
+    pub fn is_null(&self) -> bool {
+        match self {
+            KType::Guid(k)      => k.is_nil(),
+            KType::Short(k)     => *k == null::SHORT,
+            KType::Int(k)       => *k == null::INT,
+            KType::Long(k)      => *k == null::LONG,
+            KType::Real(k)      => k.is_nan(),
+            KType::Float(k)     => k.is_nan(),
+            KType::Char(k)      => *k == null::CHAR,
+            KType::Symbol(k)    => k.is_empty(),
+            KType::Timestamp(k) => k.is_null(),
+            KType::Month(k)     => k.is_null(),
+            KType::Date(k)      => k.is_null(),
+            KType::Datetime(k)  => k.is_null(),
+            KType::Timespan(k)  => k.is_null(),
+            KType::Minute(k)    => k.is_null(),
+            KType::Second(k)    => k.is_null(),
+            KType::Time(k)      => k.is_null(),
+            _ => false,
+        }
+    }
+
+    /// Whether this is a positive or negative infinity, e.g. `0W` or `-0Wd`.
+    pub fn is_infinite(&self) -> bool {
+        match self {
+            KType::Short(k)     => *k == null::SHORT_INF || *k == -null::SHORT_INF,
+            KType::Int(k)       => *k == null::INT_INF || *k == -null::INT_INF,
+            KType::Long(k)      => *k == null::LONG_INF || *k == -null::LONG_INF,
+            KType::Real(k)      => k.is_infinite(),
+            KType::Float(k)     => k.is_infinite(),
+            KType::Timestamp(k) => k.is_infinite(),
+            KType::Month(k)     => k.is_infinite(),
+            KType::Date(k)      => k.is_infinite(),
+            KType::Datetime(k)  => k.is_infinite(),
+            KType::Timespan(k)  => k.is_infinite(),
+            KType::Minute(k)    => k.is_infinite(),
+            KType::Second(k)    => k.is_infinite(),
+            KType::Time(k)      => k.is_infinite(),
+            _ => false,
+        }
+    }
+
+    // self, unless it's a null
+    fn value(&self) -> Option<&KType> {
+        if self.is_null() { None } else { Some(self) }
+    }
+
+    // The accessors below return None for a null as well as for another type.
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.value()? { KType::Boolean(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_guid(&self) -> Option<Uuid> {
+        match self.value()? { KType::Guid(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_byte(&self) -> Option<u8> {
+        match self.value()? { KType::Byte(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_short(&self) -> Option<i16> {
+        match self.value()? { KType::Short(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self.value()? { KType::Int(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_long(&self) -> Option<i64> {
+        match self.value()? { KType::Long(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_real(&self) -> Option<f32> {
+        match self.value()? { KType::Real(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self.value()? { KType::Float(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_char(&self) -> Option<char> {
+        match self.value()? { KType::Char(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self.value()? { KType::Symbol(k) => Some(k), _ => None }
+    }
+
+    pub fn as_timestamp(&self) -> Option<Timestamp> {
+        match self.value()? { KType::Timestamp(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_month(&self) -> Option<Month> {
+        match self.value()? { KType::Month(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_date(&self) -> Option<Date> {
+        match self.value()? { KType::Date(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_datetime(&self) -> Option<Datetime> {
+        match self.value()? { KType::Datetime(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_timespan(&self) -> Option<Timespan> {
+        match self.value()? { KType::Timespan(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_minute(&self) -> Option<Minute> {
+        match self.value()? { KType::Minute(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_second(&self) -> Option<Second> {
+        match self.value()? { KType::Second(k) => Some(*k), _ => None }
+    }
+
+    pub fn as_time(&self) -> Option<Time> {
+        match self.value()? { KType::Time(k) => Some(*k), _ => None }
+    }
+
+    // the unary and binary primitives of kdb+ 4.x, by the code q sends for
+    // each one, the last byte of e.g. -8!(+:) or -8!(+). Both tables run
+    // without gaps up to the last keyword k defines, hopen and cor.
     fn unary_mapping(code: &u8) -> String {
         let unary = match code {
             0 => "::",
@@ -103,11 +261,59 @@ impl KType {
            39 => "tan",
            40 => "atan",
            41 => "enlist",
-           _  =>  "",
+           42 => "var",
+           43 => "dev",
+           44 => "hopen",
+           // the gap in a projection such as {x+y}[;1]
+          255 => "",
+           _  => return format!("<unary {}>", code),
        };
        String::from(unary)
     }
 
+    // the internal functions of kdb+ 4.x, n! for each code n, by the q
+    // function they implement or otherwise by what they do
+    pub(crate) fn internal_mapping(code: i64) -> Option<&'static str> {
+        let name = match code {
+            null::LONG => "show",
+            -1 => "hsym",
+            -2 => "attr",
+            -3 => ".Q.s1",
+            -4 => "tokens",
+            -5 => "parse",
+            -6 => "eval",
+            -7 => "hcount",
+            -8 => "to bytes",
+            -9 => "from bytes",
+           -10 => "type enum",
+           -11 => "streaming execute",
+           -12 => ".Q.host",
+           -13 => ".Q.addr",
+           -14 => "quote escape",
+           -15 => "md5",
+           -16 => "ref count",
+           -17 => "flip endianness",
+           -18 => "compress bytes",
+           -19 => "compress file",
+           -21 => "compression stats",
+           -22 => "uncompressed length",
+           -23 => "memory map",
+           -24 => "reval",
+           -25 => "async broadcast",
+           -26 => "SSL",
+           -27 => "format",
+           -29 => ".j.k",
+           -30 => "deferred response",
+           -33 => "SHA-1 hash",
+           -35 => "load master key",
+           -36 => "load master key from file",
+           -38 => "socket table",
+          -120 => "memory domain",
+            _ => return None,
+        };
+        Some(name)
+    }
+
     fn operator_mapping(code: &u8) -> String {
         let op = match code {
             0 =>  ":",
@@ -147,46 +353,62 @@ impl KType {
             34 => "binr",
             35 => "cov",
             36 => "cor",
-           _  =>  "",
+           _  => return format!("<operator {}>", code),
        };
        String::from(op)
     }
 
+    /// Panics on a char above U+00FF, which `serialize_into` reports as
+    /// `ErrorKind::InvalidInput`.
     pub fn serialize(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = vec![];
+        let mut buf = Vec::with_capacity(self.serialized_len());
+        self.serialize_into(&mut buf).unwrap();
+        buf
+    }
+
+    /// Write the same bytes as `serialize` straight into `w`.
+    pub fn serialize_into<W: Write>(&self, w: &mut W) -> io::Result<()> {
         match self  {
-            KType::Boolean(n)   => vec![*n as u8],
-            KType::Guid(n)      => n.as_bytes().iter().cloned().collect(),
-            KType::Byte(n)      => vec![*n as u8],
-            KType::Short(n)     => {buf.write_i16::<LittleEndian>(*n).unwrap(); buf},
-            KType::Int(n)       => {buf.write_i32::<LittleEndian>(*n).unwrap(); buf},
-            KType::Long(n)      => {buf.write_i64::<LittleEndian>(*n).unwrap(); buf},
-            KType::Real(n)      => {buf.write_f32::<LittleEndian>(*n).unwrap(); buf},
-            KType::Float(n)     => {buf.write_f64::<LittleEndian>(*n).unwrap(); buf},
-            KType::Char(n)      => vec![*n as u8],
-            KType::String(n)    => {
-                let mut string = Vec::<u8>::with_capacity(5 + n.len());
-                string.push(0);
-                buf.write_i32::<LittleEndian>(n.len() as i32).unwrap();
-                string.append(&mut buf);
-                string.append(&mut Vec::from(n.as_bytes()));
-                string
-            },
-            KType::Symbol(n)    => {let mut sym = Vec::from(n.as_bytes());sym.push(0);sym},
-            KType::Timestamp(n) => {buf.write_i64::<LittleEndian>(n.timestamp_nanos() - 946684800000000000).unwrap(); buf},
-            KType::Month(n)     => {buf.write_i32::<LittleEndian>(n.num_days_from_ce() - 730119).unwrap(); buf},
-            KType::Date(n)      => {buf.write_i32::<LittleEndian>(n.num_days_from_ce() - 730119).unwrap(); buf},
-            KType::Datetime(n)  => {buf.write_i64::<LittleEndian>(n.timestamp_nanos() - 946684800000000000).unwrap(); buf},
-            KType::Timespan(n)  => {buf.write_i64::<LittleEndian>(n.timestamp_nanos() - 946684800000000000).unwrap(); buf},
-            KType::Minute(n)    => {buf.write_i64::<LittleEndian>(n.timestamp_nanos() - 946684800000000000).unwrap(); buf},
-            KType::Second(n)    => {buf.write_i64::<LittleEndian>(n.timestamp_nanos() - 946684800000000000).unwrap(); buf},
-            KType::Time(n)      => {buf.write_i32::<LittleEndian>((n.time().num_seconds_from_midnight() * 1000 + n.time().nanosecond() / 1_000_000) as i32).unwrap();buf},
-            KType::Unary(n)     => vec![*n as u8],
-            KType::Operator(n)  => vec![*n as u8],
+            KType::Boolean(n)   => w.write_u8(*n as u8),
+            KType::Guid(n)      => w.write_all(n.as_bytes()),
+            KType::Byte(n)      => w.write_u8(*n),
+            KType::Short(n)     => w.write_i16::<LittleEndian>(*n),
+            KType::Int(n)       => w.write_i32::<LittleEndian>(*n),
+            KType::Long(n)      => w.write_i64::<LittleEndian>(*n),
+            KType::Real(n)      => w.write_f32::<LittleEndian>(*n),
+            KType::Float(n)     => w.write_f64::<LittleEndian>(*n),
+            KType::Char(n)      => w.write_u8(char_byte(*n)?),
+            KType::Symbol(n)    => {w.write_all(n.as_bytes())?; w.write_u8(0)},
+            KType::Timestamp(n) => w.write_i64::<LittleEndian>(n.0),
+            KType::Month(n)     => w.write_i32::<LittleEndian>(n.0),
+            KType::Date(n)      => w.write_i32::<LittleEndian>(n.0),
+            KType::Datetime(n)  => w.write_f64::<LittleEndian>(n.0),
+            KType::Timespan(n)  => w.write_i64::<LittleEndian>(n.0),
+            KType::Minute(n)    => w.write_i32::<LittleEndian>(n.0),
+            KType::Second(n)    => w.write_i32::<LittleEndian>(n.0),
+            KType::Time(n)      => w.write_i32::<LittleEndian>(n.0),
+            KType::Unary(n)     => w.write_u8(*n),
+            KType::Operator(n)  => w.write_u8(*n),
+            KType::Iterator(n)  => w.write_u8(*n),
+        }
+    }
+
+    /// The number of bytes `serialize` produces, worked out without encoding.
+    pub fn serialized_len(&self) -> usize {
+        match self {
+            KType::Symbol(n) => n.as_bytes().len() + 1,
+            KType::Guid(_) => 16,
+            KType::Boolean(_) | KType::Byte(_) | KType::Char(_)
+            | KType::Unary(_) | KType::Operator(_) | KType::Iterator(_) => 1,
+            KType::Short(_) => 2,
+            KType::Int(_) | KType::Real(_) | KType::Month(_) | KType::Date(_)
+            | KType::Minute(_) | KType::Second(_) | KType::Time(_) => 4,
+            KType::Long(_) | KType::Float(_) | KType::Timestamp(_) | KType::Datetime(_)
+            | KType::Timespan(_) => 8,
         }
     }
 
-    pub fn deserialize(&self, data: &Vec<u8>) -> KType {
+    pub fn deserialize(&self, data: &[u8]) -> KType {
         match self {
             KType::Boolean(_)   => KType::Boolean(data[0] == 1),
             KType::Guid(_)      => KType::Guid(Uuid::from_slice(data).unwrap()),
@@ -197,65 +419,32 @@ impl KType {
             KType::Real(_)      => KType::Real(LittleEndian::read_f32(data)),
             KType::Float(_)     => KType::Float(LittleEndian::read_f64(data)),
             KType::Char(_)      => KType::Char(data[0] as char),
-            KType::String(_)    => KType::String(String::from_utf8(data.to_vec()).unwrap()),
-            KType::Symbol(_)    => KType::Symbol(String::from_utf8(data.to_vec()).unwrap()),
-            KType::Timestamp(_) => {
-                let dt = LittleEndian::read_i64(data) + 946684800000000000;
-                KType::Timestamp(DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp(dt / 1_000_000_000, (dt % 1_000_000_000) as u32), Utc))
-            },
-            KType::Month(_) => {
-                let dt = LittleEndian::read_i32(data) * 30 + 730119;
-                KType::Date(Date::<Utc>::from_utc(
-                    NaiveDate::from_num_days_from_ce(dt), Utc))
-            },
-            KType::Date(_) => {
-                let dt = LittleEndian::read_i32(data) + 730119;
-                KType::Date(Date::<Utc>::from_utc(
-                    NaiveDate::from_num_days_from_ce(dt), Utc))
-            },
-            KType::Datetime(_) => {
-                let dt = (LittleEndian::read_f64(data) + 10_957.0) * 86_400.0 * 1_000_000_000.0;
-                KType::Datetime(DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp((dt / 1_000_000_000.0) as i64, dt as u32 % 1_000_000_000), Utc))
-            },
-            KType::Timespan(_) => {
-                let dt = LittleEndian::read_i64(data);
-                KType::Timespan(DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp(dt / 1_000_000_000, (dt % 1_000_000_000) as u32), Utc))
-            },        
-            KType::Minute(_) => {
-                let m = LittleEndian::read_i32(data);
-                KType::Minute(DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp((m * 60) as i64, 0), Utc))
-            },   
-            KType::Second(_) => {
-                let s = LittleEndian::read_i32(data);
-                KType::Second(DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp(s as i64, 0), Utc))
-            },   
-            KType::Time(_) => {
-                let s = LittleEndian::read_i32(data);
-                KType::Time(DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp((s / 1000) as i64, 1_000_000*(s % 1_000) as u32), Utc))
-            },
-            KType::Unary(_)      => KType::Unary(data[0]),
-            KType::Operator(_)      => KType::Operator(data[0]),
+            KType::Symbol(_)    => KType::Symbol(Symbol::from_bytes(data)),
+            KType::Timestamp(_) => KType::Timestamp(Timestamp(LittleEndian::read_i64(data))),
+            KType::Month(_)     => KType::Month(Month(LittleEndian::read_i32(data))),
+            KType::Date(_)      => KType::Date(Date(LittleEndian::read_i32(data))),
+            KType::Datetime(_)  => KType::Datetime(Datetime(LittleEndian::read_f64(data))),
+            KType::Timespan(_)  => KType::Timespan(Timespan(LittleEndian::read_i64(data))),
+            KType::Minute(_)    => KType::Minute(Minute(LittleEndian::read_i32(data))),
+            KType::Second(_)    => KType::Second(Second(LittleEndian::read_i32(data))),
+            KType::Time(_)      => KType::Time(Time(LittleEndian::read_i32(data))),
+            KType::Unary(_)     => KType::Unary(data[0]),
+            KType::Operator(_)  => KType::Operator(data[0]),
+            KType::Iterator(_)  => KType::Iterator(data[0]),
         }
     }
 
     pub fn type_as_code(&self) -> i8 {
         match self {
-            KType::Boolean(_)   => -01,
-            KType::Guid(_)      => -02,
-            KType::Byte(_)      => -04,
-            KType::Short(_)     => -05,
-            KType::Int(_)       => -06,
-            KType::Long(_)      => -07,
-            KType::Real(_)      => -08,
-            KType::Float(_)     => -09,
+            KType::Boolean(_)   => -1,
+            KType::Guid(_)      => -2,
+            KType::Byte(_)      => -4,
+            KType::Short(_)     => -5,
+            KType::Int(_)       => -6,
+            KType::Long(_)      => -7,
+            KType::Real(_)      => -8,
+            KType::Float(_)     => -9,
             KType::Char(_)      => -10,
-            KType::String(_)    =>  10,
             KType::Symbol(_)    => -11,
             KType::Timestamp(_) => -12,
             KType::Month(_)     => -13,
@@ -267,6 +456,7 @@ impl KType {
             KType::Time(_)      => -19,
             KType::Unary(_)     => 101,
             KType::Operator(_)  => 102,
+            KType::Iterator(_)  => 103,
         }
     }
 }
\ No newline at end of file
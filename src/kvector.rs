@@ -0,0 +1,314 @@
+use std::fmt;
+use std::io::{self, Write};
+use uuid::Uuid;
+use byteorder::{LittleEndian, WriteBytesExt};
+use super::attribute::Attribute;
+use super::ktype::KType;
+use super::symbol::Symbol;
+use super::temporal::{Timestamp, Month, Date, Datetime, Timespan, Minute, Second, Time};
+
+/// A simple list, held as a plain vector of its elements rather than as a
+/// list of boxed atoms.
+///
+/// Temporal vectors hold the raw kdb+ values, i.e. offsets from the kdb+
+/// epoch of 2000.01.01 in the unit of the type, so they round-trip exactly.
+/// `get` wraps them in the matching type from the `temporal` module.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KVector {
+    Boolean(Vec<bool>),
+    Guid(Vec<Uuid>),
+    Byte(Vec<u8>),
+    Short(Vec<i16>),
+    Int(Vec<i32>),
+    Long(Vec<i64>),
+    Real(Vec<f32>),
+    Float(Vec<f64>),
+    /// A string. q chars are single bytes, usually but not always UTF-8.
+    Char(Vec<u8>),
+    Symbol(Vec<Symbol>),
+    Timestamp(Vec<i64>),
+    Month(Vec<i32>),
+    Date(Vec<i32>),
+    Datetime(Vec<f64>),
+    Timespan(Vec<i64>),
+    Minute(Vec<i32>),
+    Second(Vec<i32>),
+    Time(Vec<i32>),
+}
+
+impl fmt::Display for KVector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let KVector::Char(s) = self {
+            return fmt_string(s, f)
+        };
+        if self.is_empty() {
+            return write!(f, "`{}$()", self.type_name())
+        };
+        let list: Vec<String> = self.iter().map(|x|format!("{}", x)).collect();
+        let needs_enlist = if 1 == list.len() { "enlist " } else { "" };
+        write!(f, "({}{})", needs_enlist, list.join(";"))
+    }
+}
+
+impl KVector {
+    /// An empty vector for a positive kdb+ type code.
+    pub fn new(code: i8) -> Option<KVector> {
+        let vector = match code {
+             1 => KVector::Boolean(vec![]),
+             2 => KVector::Guid(vec![]),
+             4 => KVector::Byte(vec![]),
+             5 => KVector::Short(vec![]),
+             6 => KVector::Int(vec![]),
+             7 => KVector::Long(vec![]),
+             8 => KVector::Real(vec![]),
+             9 => KVector::Float(vec![]),
+            10 => KVector::Char(vec![]),
+            11 => KVector::Symbol(vec![]),
+            12 => KVector::Timestamp(vec![]),
+            13 => KVector::Month(vec![]),
+            14 => KVector::Date(vec![]),
+            15 => KVector::Datetime(vec![]),
+            16 => KVector::Timespan(vec![]),
+            17 => KVector::Minute(vec![]),
+            18 => KVector::Second(vec![]),
+            19 => KVector::Time(vec![]),
+             _ => return None,
+        };
+        Some(vector)
+    }
+
+    pub fn type_as_code(&self) -> i8 {
+        match self {
+            KVector::Boolean(_)   => 1,
+            KVector::Guid(_)      => 2,
+            KVector::Byte(_)      => 4,
+            KVector::Short(_)     => 5,
+            KVector::Int(_)       => 6,
+            KVector::Long(_)      => 7,
+            KVector::Real(_)      => 8,
+            KVector::Float(_)     => 9,
+            KVector::Char(_)      => 10,
+            KVector::Symbol(_)    => 11,
+            KVector::Timestamp(_) => 12,
+            KVector::Month(_)     => 13,
+            KVector::Date(_)      => 14,
+            KVector::Datetime(_)  => 15,
+            KVector::Timespan(_)  => 16,
+            KVector::Minute(_)    => 17,
+            KVector::Second(_)    => 18,
+            KVector::Time(_)      => 19,
+        }
+    }
+
+    // the name q casts with, as in `` `long$() ``
+    fn type_name(&self) -> &'static str {
+        match self {
+            KVector::Boolean(_)   => "boolean",
+            KVector::Guid(_)      => "guid",
+            KVector::Byte(_)      => "byte",
+            KVector::Short(_)     => "short",
+            KVector::Int(_)       => "int",
+            KVector::Long(_)      => "long",
+            KVector::Real(_)      => "real",
+            KVector::Float(_)     => "float",
+            KVector::Char(_)      => "char",
+            KVector::Symbol(_)    => "symbol",
+            KVector::Timestamp(_) => "timestamp",
+            KVector::Month(_)     => "month",
+            KVector::Date(_)      => "date",
+            KVector::Datetime(_)  => "datetime",
+            KVector::Timespan(_)  => "timespan",
+            KVector::Minute(_)    => "minute",
+            KVector::Second(_)    => "second",
+            KVector::Time(_)      => "time",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            KVector::Boolean(v)   => v.len(),
+            KVector::Guid(v)      => v.len(),
+            KVector::Byte(v)      => v.len(),
+            KVector::Short(v)     => v.len(),
+            KVector::Int(v)       => v.len(),
+            KVector::Long(v)      => v.len(),
+            KVector::Real(v)      => v.len(),
+            KVector::Float(v)     => v.len(),
+            KVector::Char(v)      => v.len(),
+            KVector::Symbol(v)    => v.len(),
+            KVector::Timestamp(v) => v.len(),
+            KVector::Month(v)     => v.len(),
+            KVector::Date(v)      => v.len(),
+            KVector::Datetime(v)  => v.len(),
+            KVector::Timespan(v)  => v.len(),
+            KVector::Minute(v)    => v.len(),
+            KVector::Second(v)    => v.len(),
+            KVector::Time(v)      => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The text of a char vector, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KVector::Char(s) => std::str::from_utf8(s).ok(),
+            _ => None,
+        }
+    }
+
+    /// The element at `index` as an atom.
+    pub fn get(&self, index: usize) -> Option<KType> {
+        if index >= self.len() {
+            return None
+        };
+        let atom = match self {
+            KVector::Boolean(v)   => KType::Boolean(v[index]),
+            KVector::Guid(v)      => KType::Guid(v[index]),
+            KVector::Byte(v)      => KType::Byte(v[index]),
+            KVector::Short(v)     => KType::Short(v[index]),
+            KVector::Int(v)       => KType::Int(v[index]),
+            KVector::Long(v)      => KType::Long(v[index]),
+            KVector::Real(v)      => KType::Real(v[index]),
+            KVector::Float(v)     => KType::Float(v[index]),
+            KVector::Char(v)      => KType::Char(v[index] as char),
+            KVector::Symbol(v)    => KType::Symbol(v[index].clone()),
+            KVector::Timestamp(v) => KType::Timestamp(Timestamp(v[index])),
+            KVector::Month(v)     => KType::Month(Month(v[index])),
+            KVector::Date(v)      => KType::Date(Date(v[index])),
+            KVector::Datetime(v)  => KType::Datetime(Datetime(v[index])),
+            KVector::Timespan(v)  => KType::Timespan(Timespan(v[index])),
+            KVector::Minute(v)    => KType::Minute(Minute(v[index])),
+            KVector::Second(v)    => KType::Second(Second(v[index])),
+            KVector::Time(v)      => KType::Time(Time(v[index])),
+        };
+        Some(atom)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = KType> + '_ {
+        (0..self.len()).map(move |i| self.get(i).unwrap())
+    }
+
+    // bytes per element on the wire, or None for null terminated symbols
+    pub(crate) fn width(&self) -> Option<usize> {
+        match self {
+            KVector::Boolean(_) | KVector::Byte(_) | KVector::Char(_) => Some(1),
+            KVector::Guid(_) => Some(16),
+            KVector::Short(_) => Some(2),
+            KVector::Int(_) | KVector::Real(_) | KVector::Month(_) | KVector::Date(_)
+            | KVector::Minute(_) | KVector::Second(_) | KVector::Time(_) => Some(4),
+            KVector::Long(_) | KVector::Float(_) | KVector::Timestamp(_)
+            | KVector::Datetime(_) | KVector::Timespan(_) => Some(8),
+            KVector::Symbol(_) => None,
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.serialized_len());
+        self.serialize_into(&mut result).unwrap();
+        result
+    }
+
+    /// Write the same bytes as `serialize` straight into `w`.
+    pub fn serialize_into<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.serialize_with(Attribute::None, w)
+    }
+
+    pub(crate) fn serialize_with<W: Write>(&self, attribute: Attribute, w: &mut W) -> io::Result<()> {
+        // 1 byte for attribute
+        w.write_u8(attribute.code())?;
+        // 4 bytes for length
+        w.write_i32::<LittleEndian>(self.len() as i32)?;
+        // ? bytes for data
+        match self {
+            KVector::Boolean(v)   => v.iter().try_for_each(|b| w.write_u8(*b as u8)),
+            KVector::Guid(v)      => v.iter().try_for_each(|g| w.write_all(g.as_bytes())),
+            KVector::Byte(v)      => w.write_all(v),
+            KVector::Short(v)     => v.iter().try_for_each(|n| w.write_i16::<LittleEndian>(*n)),
+            KVector::Int(v)       => v.iter().try_for_each(|n| w.write_i32::<LittleEndian>(*n)),
+            KVector::Long(v)      => v.iter().try_for_each(|n| w.write_i64::<LittleEndian>(*n)),
+            KVector::Real(v)      => v.iter().try_for_each(|n| w.write_f32::<LittleEndian>(*n)),
+            KVector::Float(v)     => v.iter().try_for_each(|n| w.write_f64::<LittleEndian>(*n)),
+            KVector::Char(v)      => w.write_all(v),
+            KVector::Symbol(v)    => v.iter().try_for_each(|s| {w.write_all(s.as_bytes())?; w.write_u8(0)}),
+            KVector::Timestamp(v) => v.iter().try_for_each(|n| w.write_i64::<LittleEndian>(*n)),
+            KVector::Month(v)     => v.iter().try_for_each(|n| w.write_i32::<LittleEndian>(*n)),
+            KVector::Date(v)      => v.iter().try_for_each(|n| w.write_i32::<LittleEndian>(*n)),
+            KVector::Datetime(v)  => v.iter().try_for_each(|n| w.write_f64::<LittleEndian>(*n)),
+            KVector::Timespan(v)  => v.iter().try_for_each(|n| w.write_i64::<LittleEndian>(*n)),
+            KVector::Minute(v)    => v.iter().try_for_each(|n| w.write_i32::<LittleEndian>(*n)),
+            KVector::Second(v)    => v.iter().try_for_each(|n| w.write_i32::<LittleEndian>(*n)),
+            KVector::Time(v)      => v.iter().try_for_each(|n| w.write_i32::<LittleEndian>(*n)),
+        }
+    }
+
+    /// The number of bytes `serialize` produces, worked out without encoding.
+    pub fn serialized_len(&self) -> usize {
+        let data = match (self, self.width()) {
+            (KVector::Symbol(v), _) => v.iter().map(|s| s.as_bytes().len() + 1).sum(),
+            (_, Some(width)) => self.len() * width,
+            (_, None) => 0,
+        };
+        5 + data
+    }
+}
+
+impl From<Vec<bool>> for KVector {
+    fn from(v: Vec<bool>) -> KVector { KVector::Boolean(v) }
+}
+
+impl From<Vec<Uuid>> for KVector {
+    fn from(v: Vec<Uuid>) -> KVector { KVector::Guid(v) }
+}
+
+impl From<Vec<u8>> for KVector {
+    fn from(v: Vec<u8>) -> KVector { KVector::Byte(v) }
+}
+
+impl From<Vec<i16>> for KVector {
+    fn from(v: Vec<i16>) -> KVector { KVector::Short(v) }
+}
+
+impl From<Vec<i32>> for KVector {
+    fn from(v: Vec<i32>) -> KVector { KVector::Int(v) }
+}
+
+impl From<Vec<i64>> for KVector {
+    fn from(v: Vec<i64>) -> KVector { KVector::Long(v) }
+}
+
+impl From<Vec<f32>> for KVector {
+    fn from(v: Vec<f32>) -> KVector { KVector::Real(v) }
+}
+
+impl From<Vec<f64>> for KVector {
+    fn from(v: Vec<f64>) -> KVector { KVector::Float(v) }
+}
+
+impl From<&str> for KVector {
+    fn from(s: &str) -> KVector { KVector::Char(s.as_bytes().to_vec()) }
+}
+
+impl From<String> for KVector {
+    fn from(s: String) -> KVector { KVector::Char(s.into_bytes()) }
+}
+
+impl From<Vec<String>> for KVector {
+    fn from(v: Vec<String>) -> KVector { KVector::Symbol(v.into_iter().map(Symbol::from).collect()) }
+}
+
+impl From<Vec<Symbol>> for KVector {
+    fn from(v: Vec<Symbol>) -> KVector { KVector::Symbol(v) }
+}
+
+// q string syntax, e.g. "a\"b", with a single char as `enlist "a"`
+fn fmt_string(s: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+    let text = String::from_utf8_lossy(s).replace('\\', "\\\\").replace('"', "\\\"");
+    if s.len() == 1 {
+        write!(f, "(enlist \"{}\")", text)
+    } else {
+        write!(f, "\"{}\"", text)
+    }
+}
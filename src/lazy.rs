@@ -0,0 +1,337 @@
+use std::ops::Range;
+use std::io::Error;
+use crate::KObj;
+use super::decode::{Decoder, invalid, is_unsupported, is_unsupported_type};
+use super::rows::Rows;
+use super::ktype::KType;
+use super::symbol::{Symbol, SymbolInterner};
+use super::text::TextMode;
+use super::view::{KView, VectorView};
+
+/// A message that has been framed but not decoded, see `Kdb::read_lazy`.
+///
+/// Tables and dictionaries keep the raw message body and only decode a
+/// column, or the keys or values, when it is asked for. Anything else is
+/// decoded straight away.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lazy {
+    Table(LazyTable),
+    Dict(LazyDict),
+    Value(KObj),
+}
+
+/// A table whose columns are decoded on demand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LazyTable {
+    body: Vec<u8>,
+    names: Vec<Symbol>,
+    // where each column, including its type byte, sits in `body`
+    columns: Vec<Range<usize>>,
+}
+
+/// A dictionary whose keys and values are decoded on demand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LazyDict {
+    body: Vec<u8>,
+    keys: Range<usize>,
+    values: Range<usize>,
+}
+
+impl Lazy {
+    /// Frame a message body, i.e. everything after the 8 byte header.
+    pub fn new(body: Vec<u8>) -> Result<Lazy, Error> {
+        match body.first() {
+            Some(98) => Ok(Lazy::Table(LazyTable::new(body)?)),
+            Some(99) => Ok(Lazy::Dict(LazyDict::new(body)?)),
+            _ => Ok(Lazy::Value(KView::decode(&body)?.into_kobj())),
+        }
+    }
+
+    /// Decode whatever hasn't been decoded yet.
+    pub fn into_kobj(self) -> Result<KObj, Error> {
+        match self {
+            Lazy::Table(t) => t.decode(),
+            Lazy::Dict(d) => Ok(KView::decode(&d.body)?.into_kobj()),
+            Lazy::Value(k) => Ok(k),
+        }
+    }
+
+    /// The buffer holding an undecoded table or dictionary, e.g. to give it
+    /// back to a `BufferPool`.
+    pub fn into_body(self) -> Option<Vec<u8>> {
+        match self {
+            Lazy::Table(t) => Some(t.body),
+            Lazy::Dict(d) => Some(d.body),
+            Lazy::Value(_) => None,
+        }
+    }
+}
+
+impl LazyTable {
+    fn new(body: Vec<u8>) -> Result<LazyTable, Error> {
+        let (names, columns) = frame_table(&body)?;
+        Ok(LazyTable { body, names, columns })
+    }
+
+    pub fn names(&self) -> &[Symbol] {
+        &self.names
+    }
+
+    /// The number of columns.
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Decode the column called `name`, or None if there is no such column.
+    pub fn column(&self, name: &str) -> Option<Result<KObj, Error>> {
+        let index = self.names.iter().position(|n| n == name)?;
+        self.column_at(index)
+    }
+
+    pub fn column_at(&self, index: usize) -> Option<Result<KObj, Error>> {
+        Some(self.column_view(index)?.map(KView::into_kobj))
+    }
+
+    /// Decode a column in place, borrowing from the table's buffer.
+    pub fn column_view(&self, index: usize) -> Option<Result<KView<'_>, Error>> {
+        let range = self.columns.get(index)?.clone();
+        Some(KView::decode(&self.body[range]))
+    }
+
+    /// Decode the whole table. With the `parallel` feature the columns are
+    /// decoded in parallel.
+    pub fn decode(&self) -> Result<KObj, Error> {
+        decode_columns(&self.body, &self.names, &self.columns, None)
+    }
+
+    /// Iterate the table row by row. Fails with `ErrorKind::Unsupported` if a
+    /// column isn't a simple list, e.g. a column of strings.
+    pub fn rows(&self) -> Result<Rows<'_>, Error> {
+        let columns = self.columns.iter()
+            .map(|range| KView::decode(&self.body[range.clone()]))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Rows::new(&self.names, columns)
+    }
+}
+
+impl LazyDict {
+    fn new(body: Vec<u8>) -> Result<LazyDict, Error> {
+        let mut decoder = Decoder::new(&body);
+        decoder.take(1)?;
+        let start = decoder.position();
+        decoder.skip()?;
+        let keys = start..decoder.position();
+        decoder.skip()?;
+        let values = keys.end..decoder.position();
+        Ok(LazyDict { body, keys, values })
+    }
+
+    pub fn keys(&self) -> Result<KObj, Error> {
+        Ok(self.keys_view()?.into_kobj())
+    }
+
+    pub fn values(&self) -> Result<KObj, Error> {
+        Ok(self.values_view()?.into_kobj())
+    }
+
+    pub fn keys_view(&self) -> Result<KView<'_>, Error> {
+        KView::decode(&self.body[self.keys.clone()])
+    }
+
+    pub fn values_view(&self) -> Result<KView<'_>, Error> {
+        KView::decode(&self.body[self.values.clone()])
+    }
+}
+
+// find the column names, and where each column sits in a table's body
+fn frame_table(body: &[u8]) -> Result<(Vec<Symbol>, Vec<Range<usize>>), Error> {
+    let mut decoder = Decoder::new(body);
+    // table type, attribute, then the type byte of the column dictionary
+    decoder.take(3)?;
+    let names: Vec<Symbol> = match decoder.decode()? {
+        KView::Vector(_, VectorView::Symbol(names)) => names.iter().map(Symbol::new).collect(),
+        _ => return Err(invalid("table column names must be symbols")),
+    };
+    if decoder.read_type()? != 0 {
+        return Err(invalid("table columns must be a general list"))
+    };
+    decoder.take(1)?;
+    if decoder.read_len()? != names.len() {
+        return Err(invalid("table has a different number of names and columns"))
+    };
+    let mut columns = Vec::with_capacity(names.len());
+    for _ in 0..names.len() {
+        let start = decoder.position();
+        decoder.skip()?;
+        columns.push(start..decoder.position());
+    };
+    Ok((names, columns))
+}
+
+fn decode_columns(body: &[u8], names: &[Symbol], columns: &[Range<usize>], interner: Option<&mut SymbolInterner>) -> Result<KObj, Error> {
+    let (names, columns) = match interner {
+        // the interner can't be shared between threads, so the columns are
+        // only decoded to views in parallel, then interned one at a time
+        Some(interner) => {
+            let views = each_column(columns, |range| KView::decode(&body[range.clone()]))?;
+            let names = names.iter().map(|n| interner.intern(n)).collect::<Vec<_>>();
+            (names, views.into_iter().map(|v| v.into_kobj_interned(interner)).collect())
+        },
+        None => (names.to_vec(), each_column(columns, |range| Ok(KView::decode(&body[range.clone()])?.into_kobj()))?),
+    };
+    let names = names.into_iter().map(|n| KObj::Atom(KType::Symbol(n))).collect();
+    Ok(KObj::Table(names, columns))
+}
+
+// run `decode` over every column, in parallel with the `parallel` feature
+fn each_column<T, F>(columns: &[Range<usize>], decode: F) -> Result<Vec<T>, Error>
+where T: Send, F: Fn(&Range<usize>) -> Result<T, Error> + Sync + Send {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        columns.par_iter().map(decode).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    columns.iter().map(decode).collect()
+}
+
+/// Decode a whole message body into an owned value, taking symbols from
+/// `interner` if there is one. With the `parallel` feature, tables are framed
+/// first so their columns can be decoded in parallel.
+///
+/// Views need their text to be UTF-8, so other text modes decode straight to
+/// owned values instead, and never in parallel.
+pub(crate) fn decode_owned(body: &[u8], interner: Option<&mut SymbolInterner>, text: TextMode) -> Result<KObj, Error> {
+    if text != TextMode::Strict {
+        return Decoder::new(body).decode_owned(text, interner)
+    };
+    match interner {
+        _ if cfg!(feature = "parallel") && body.first() == Some(&98) => {
+            let (names, columns) = frame_table(body)?;
+            decode_columns(body, &names, &columns, interner)
+        },
+        Some(interner) => Ok(KView::decode(body)?.into_kobj_interned(interner)),
+        None => Ok(KView::decode(body)?.into_kobj()),
+    }
+}
+
+/// As `decode_owned`, but a message holding a type rsq can't read becomes
+/// `KObj::Opaque`. A value of such a type can't be stepped over, so when it
+/// is nested in a list, dictionary or table the whole message is kept.
+pub(crate) fn decode_lenient(body: &[u8], interner: Option<&mut SymbolInterner>, text: TextMode) -> Result<KObj, Error> {
+    let (code, bytes) = match body.split_first() {
+        Some(split) => split,
+        None => return decode_owned(body, interner, text),
+    };
+    let opaque = || KObj::Opaque { type_code: *code as i8, bytes: bytes.to_vec() };
+    if is_unsupported_type(*code as i8) {
+        return Ok(opaque())
+    };
+    match decode_owned(body, interner, text) {
+        Err(e) if is_unsupported(&e) => Ok(opaque()),
+        msg => msg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attribute, KVector};
+
+    // ([] sym:`a`b; px:1 2f; qty:10 20i)
+    fn table() -> Vec<u8> {
+        vec![98, 0, 99, 11, 0, 3, 0, 0, 0, 115, 121, 109, 0, 112, 120, 0, 113, 116, 121, 0,
+            0, 0, 3, 0, 0, 0, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0, 9, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 0, 64,
+            6, 0, 2, 0, 0, 0, 10, 0, 0, 0, 20, 0, 0, 0]
+    }
+
+    #[test]
+    fn decodes_only_requested_column() {
+        let mut body = table();
+        // corrupt the sym column, which is never asked for
+        body[32] = 255;
+        let table = match Lazy::new(body).unwrap() {
+            Lazy::Table(t) => t,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(table.names(), &["sym", "px", "qty"]);
+        assert_eq!(table.width(), 3);
+        assert_eq!(table.column("px").unwrap().unwrap(), KObj::Vector(Attribute::None, KVector::Float(vec![1., 2.])));
+        assert_eq!(table.column_at(2).unwrap().unwrap(), KObj::Vector(Attribute::None, KVector::Int(vec![10, 20])));
+        assert!(table.column_at(0).unwrap().is_err());
+        assert!(table.column("missing").is_none());
+    }
+
+    #[test]
+    fn matches_eager_decoding() {
+        let eager = KView::decode(&table()).unwrap().into_kobj();
+        assert_eq!(Lazy::new(table()).unwrap().into_kobj().unwrap(), eager);
+    }
+
+    #[test]
+    fn frames_dict() {
+        // `a`b!1 2
+        let body = vec![99, 11, 0, 2, 0, 0, 0, 97, 0, 98, 0, 7, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+        let dict = match Lazy::new(body).unwrap() {
+            Lazy::Dict(d) => d,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(dict.keys().unwrap(), KObj::Vector(Attribute::None, KVector::Symbol(vec!["a".into(), "b".into()])));
+        assert_eq!(dict.values().unwrap(), KObj::Vector(Attribute::None, KVector::Long(vec![1, 2])));
+    }
+
+    #[test]
+    fn decode_owned_matches_view() {
+        let view = KView::decode(&table()).unwrap().into_kobj();
+        for text in [TextMode::Strict, TextMode::Lossy, TextMode::Bytes] {
+            assert_eq!(decode_owned(&table(), None, text).unwrap(), view);
+        }
+    }
+
+    #[test]
+    fn decode_owned_interns_tables() {
+        let mut interner = SymbolInterner::new();
+        let interned = decode_owned(&table(), Some(&mut interner), TextMode::Strict).unwrap();
+        assert_eq!(interned, decode_owned(&table(), None, TextMode::Strict).unwrap());
+        // `sym`px`qty and `a`b
+        assert_eq!(interner.len(), 5);
+    }
+
+    #[test]
+    fn decodes_other_values_eagerly() {
+        assert_eq!(Lazy::new(vec![245, 97, 0]).unwrap(), Lazy::Value(KObj::Atom(KType::Symbol("a".into()))));
+    }
+
+    #[test]
+    fn streams_rows() {
+        let table = match Lazy::new(table()).unwrap() {
+            Lazy::Table(t) => t,
+            other => panic!("unexpected {:?}", other),
+        };
+        let rows = table.rows().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.collect::<Vec<_>>(), vec![
+            vec![KView::Symbol("a"), KView::Atom(KType::Float(1.)), KView::Atom(KType::Int(10))],
+            vec![KView::Symbol("b"), KView::Atom(KType::Float(2.)), KView::Atom(KType::Int(20))],
+        ]);
+    }
+
+    #[test]
+    fn refuses_to_stream_general_list_columns() {
+        // ([] s:("ab";"c"))
+        let body = vec![98, 0, 99, 11, 0, 1, 0, 0, 0, 115, 0, 0, 0, 1, 0, 0, 0,
+            0, 0, 2, 0, 0, 0, 10, 0, 2, 0, 0, 0, 97, 98, 10, 0, 1, 0, 0, 0, 99];
+        let table = match Lazy::new(body).unwrap() {
+            Lazy::Table(t) => t,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(table.rows().err().unwrap().kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn rejects_truncated_table() {
+        let mut body = table();
+        body.truncate(50);
+        assert_eq!(Lazy::new(body).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -37,17 +37,23 @@
 //! 
 //! ```no_run
 //! use rsq::{Kdb, KObj, KType};
+//! use std::io::ErrorKind;
 //! use std::net::TcpStream;
 //! let mut kdb = Kdb::new("localhost", 5001, "username", "password");
 //! 
-//! kdb.send_async(&KObj::List(vec![
-//!     KObj::Atom(KType::Symbol(".u.sub".to_string())),
-//!     KObj::Atom(KType::Symbol("trade".to_string())),
-//!     KObj::Atom(KType::Symbol("".to_string()))
+//! kdb.send_async(&KObj::list(vec![
+//!     KObj::Atom(KType::Symbol(".u.sub".into())),
+//!     KObj::Atom(KType::Symbol("trade".into())),
+//!     KObj::Atom(KType::Symbol("".into()))
 //! ])).unwrap();
 //! 
 //! loop {
-//!     println!("{}",kdb.read());
+//!     match kdb.read() {
+//!         Ok(msg) => println!("{}", msg),
+//!         // the tickerplant closed the connection
+//!         Err(e) if e.kind() == ErrorKind::ConnectionAborted => break,
+//!         Err(e) => panic!("{}", e),
+//!     }
 //! };
 //! ```
 //! **Output**
@@ -56,10 +62,43 @@
 //! (`upd;`trade;flip (`time;`sym;`price;`size)!((enlist 20:59:00.000);(enlist `TSLA);(enlist 653.2f);(enlist 30j)))
 //! (`upd;`trade;flip (`time;`sym;`price;`size)!((enlist 20:59:30.000);(enlist `TSLA);(enlist 653.1f);(enlist 100j)))
 //! ```
+mod adverb;
+mod attribute;
+mod buffer;
+mod decode;
+mod endpoint;
+mod enumeration;
+mod failover;
+#[cfg(test)]
+mod fake_q;
+mod flush;
 mod header;
 mod kdb;
 mod ktype;
 mod kobj;
+mod kvector;
+mod lazy;
+pub mod null;
+mod pool;
+mod rows;
+mod symbol;
+pub mod temporal;
+mod text;
+mod view;
+pub use adverb::Adverb;
+pub use attribute::Attribute;
+pub use buffer::BufferPool;
+pub use endpoint::Endpoint;
+pub use enumeration::Enumeration;
+pub use failover::{FailoverKdb, FailoverMode, FailoverEvent};
+pub use flush::FlushPolicy;
 pub use ktype::KType;
 pub use kobj::KObj;
-pub use kdb::Kdb;
\ No newline at end of file
+pub use kvector::KVector;
+pub use lazy::{Lazy, LazyTable, LazyDict};
+pub use kdb::{Kdb, ConnectionState};
+pub use pool::{KdbPool, PooledKdb};
+pub use rows::Rows;
+pub use symbol::{Symbol, SymbolInterner};
+pub use text::TextMode;
+pub use view::{KView, VectorView, EnumView, Column, Symbols, FixedWidth};
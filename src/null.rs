@@ -0,0 +1,34 @@
+//! kdb+'s in-band nulls and infinities for the non-temporal types. The
+//! temporal types have their own, e.g. `temporal::Date::NULL`.
+//!
+//! Booleans and bytes have no null. Float nulls are NaN, so compare them with
+//! `is_nan` rather than `==`.
+use uuid::Uuid;
+
+/// `0Ng`
+pub const GUID: Uuid = Uuid::nil();
+/// `0Nh`
+pub const SHORT: i16 = i16::MIN;
+/// `0Ni`
+pub const INT: i32 = i32::MIN;
+/// `0N`, or `0Nj`
+pub const LONG: i64 = i64::MIN;
+/// `0Ne`
+pub const REAL: f32 = f32::NAN;
+/// `0n`
+pub const FLOAT: f64 = f64::NAN;
+/// `" "`
+pub const CHAR: char = ' ';
+/// `` ` ``
+pub const SYMBOL: &str = "";
+
+/// `0Wh`, negated for `-0Wh`
+pub const SHORT_INF: i16 = i16::MAX;
+/// `0Wi`, negated for `-0Wi`
+pub const INT_INF: i32 = i32::MAX;
+/// `0W`, negated for `-0W`
+pub const LONG_INF: i64 = i64::MAX;
+/// `0We`
+pub const REAL_INF: f32 = f32::INFINITY;
+/// `0w`
+pub const FLOAT_INF: f64 = f64::INFINITY;
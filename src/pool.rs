@@ -0,0 +1,253 @@
+use std::net::TcpStream;
+use std::io::{ErrorKind, Error};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use super::endpoint::Endpoint;
+use super::kdb::Kdb;
+
+type TcpKdb = Kdb<TcpStream, TcpStream>;
+
+/// A bounded pool of `Kdb` connections spread across one or more endpoints.
+///
+/// Idle connections are pinged before being handed out and evicted if the
+/// ping fails. Connections are lent out as `PooledKdb` guards which return
+/// the connection to the pool when dropped, unless it has failed or has
+/// messages waiting that were never read, in which case it is closed.
+pub struct KdbPool {
+    endpoints: Vec<Endpoint>,
+    max_size: usize,
+    timeout: Option<Duration>,
+    state: Mutex<PoolState>,
+    released: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<TcpKdb>,
+    open: usize,
+    next_endpoint: usize,
+}
+
+pub struct PooledKdb<'a> {
+    pool: &'a KdbPool,
+    kdb: Option<TcpKdb>,
+}
+
+impl KdbPool {
+    pub fn new(endpoints: Vec<Endpoint>, max_size: usize) -> KdbPool {
+        assert!(!endpoints.is_empty(), "a pool needs at least one endpoint");
+        assert!(max_size > 0, "a pool needs room for at least one connection");
+        KdbPool {
+            endpoints,
+            max_size,
+            timeout: None,
+            state: Mutex::new(PoolState { idle: vec![], open: 0, next_endpoint: 0 }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Fail `get` with `ErrorKind::TimedOut` instead of blocking forever
+    /// when every connection is lent out.
+    pub fn with_timeout(mut self, timeout: Duration) -> KdbPool {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Number of connections currently open, whether idle or lent out.
+    pub fn size(&self) -> usize {
+        self.lock().open
+    }
+
+    pub fn idle(&self) -> usize {
+        self.lock().idle.len()
+    }
+
+    /// Borrow a healthy connection, opening a new one if the pool has room
+    /// and waiting for one to be returned otherwise.
+    pub fn get(&self) -> Result<PooledKdb<'_>, Error> {
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        let mut state = self.lock();
+        loop {
+            if let Some(mut kdb) = state.idle.pop() {
+                drop(state);
+                if kdb.ping().is_ok() {
+                    return Ok(PooledKdb { pool: self, kdb: Some(kdb) })
+                };
+                self.evict(kdb);
+                state = self.lock();
+                continue
+            };
+
+            if state.open < self.max_size {
+                state.open += 1;
+                let first = state.next_endpoint;
+                state.next_endpoint = (first + 1) % self.endpoints.len();
+                drop(state);
+                return match self.connect(first) {
+                    Ok(kdb) => Ok(PooledKdb { pool: self, kdb: Some(kdb) }),
+                    Err(e) => {
+                        self.lock().open -= 1;
+                        self.released.notify_one();
+                        Err(e)
+                    }
+                }
+            };
+
+            state = match deadline {
+                None => self.released.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::new(ErrorKind::TimedOut, "timed out waiting for a pooled connection"))
+                    };
+                    self.released.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+
+    /// Ping every idle connection and evict the ones that fail.
+    pub fn health_check(&self) {
+        let idle: Vec<TcpKdb> = self.lock().idle.drain(..).collect();
+        for mut kdb in idle {
+            if kdb.ping().is_ok() {
+                self.release(kdb);
+            } else {
+                self.evict(kdb);
+            }
+        }
+    }
+
+    // try each endpoint once, starting from `first`, returning the last error
+    fn connect(&self, first: usize) -> Result<TcpKdb, Error> {
+        let mut last_err = Error::new(ErrorKind::NotConnected, "no endpoints configured");
+        for i in 0..self.endpoints.len() {
+            let endpoint = &self.endpoints[(first + i) % self.endpoints.len()];
+            let mut kdb = Kdb::from_endpoint(endpoint);
+            match kdb.open() {
+                Ok(()) => return Ok(kdb),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    fn release(&self, kdb: TcpKdb) {
+        self.lock().idle.push(kdb);
+        self.released.notify_one();
+    }
+
+    fn evict(&self, mut kdb: TcpKdb) {
+        kdb.close().ok();
+        self.lock().open -= 1;
+        self.released.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl PooledKdb<'_> {
+    /// Close the connection instead of returning it to the pool, e.g. after
+    /// an IO error has left it in an unknown state.
+    pub fn discard(mut self) {
+        if let Some(kdb) = self.kdb.take() {
+            self.pool.evict(kdb);
+        }
+    }
+}
+
+impl Deref for PooledKdb<'_> {
+    type Target = TcpKdb;
+
+    fn deref(&self) -> &TcpKdb {
+        self.kdb.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledKdb<'_> {
+    fn deref_mut(&mut self) -> &mut TcpKdb {
+        self.kdb.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledKdb<'_> {
+    fn drop(&mut self) {
+        if let Some(mut kdb) = self.kdb.take() {
+            // anything left unread would be taken by the next borrower as
+            // the reply to its own query
+            if kdb.is_connected() && !kdb.has_unread_input().unwrap_or(true) {
+                self.pool.release(kdb);
+            } else {
+                self.pool.evict(kdb);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_q;
+    use std::io::{BufRead, Write};
+
+    #[test]
+    fn connections_are_reused() {
+        let pool = KdbPool::new(vec![fake_q::spawn()], 2);
+        {
+            let mut kdb = pool.get().unwrap();
+            kdb.ping().unwrap();
+            assert_eq!(pool.idle(), 0);
+        }
+        assert_eq!(pool.idle(), 1);
+        let _kdb = pool.get().unwrap();
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn pool_is_bounded() {
+        let pool = KdbPool::new(vec![fake_q::spawn()], 1).with_timeout(Duration::from_millis(50));
+        let kdb = pool.get().unwrap();
+        assert_eq!(pool.get().err().unwrap().kind(), ErrorKind::TimedOut);
+        kdb.discard();
+        assert_eq!(pool.size(), 0);
+        assert!(pool.get().is_ok());
+    }
+
+    #[test]
+    fn skips_dead_endpoints() {
+        let pool = KdbPool::new(vec![fake_q::dead(), fake_q::spawn()], 2);
+        assert!(pool.get().is_ok());
+    }
+
+    #[test]
+    fn failed_connections_are_evicted() {
+        let pool = KdbPool::new(vec![fake_q::spawn_once()], 1);
+        {
+            let mut kdb = pool.get().unwrap();
+            kdb.ping().unwrap();
+            // the fake q has hung up after its one reply
+            assert!(kdb.ping().is_err());
+            assert!(!kdb.is_connected());
+        }
+        assert_eq!((pool.size(), pool.idle()), (0, 0));
+    }
+
+    #[test]
+    fn connections_with_unread_replies_are_evicted() {
+        let pool = KdbPool::new(vec![fake_q::spawn()], 1);
+        {
+            let mut kdb = pool.get().unwrap();
+            // a sync message whose reply is left unread
+            kdb.writer().write_all(&[1, 1, 0, 0, 10, 0, 0, 0, 255, 1]).unwrap();
+            kdb.flush().unwrap();
+            kdb.reader().fill_buf().unwrap();
+        }
+        assert_eq!((pool.size(), pool.idle()), (0, 0));
+    }
+}
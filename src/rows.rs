@@ -0,0 +1,108 @@
+use std::io::{ErrorKind, Error};
+use std::str::SplitTerminator;
+use crate::KObj;
+use super::decode::invalid;
+use super::ktype::KType;
+use super::symbol::Symbol;
+use super::kvector::KVector;
+use super::view::{KView, VectorView};
+
+/// The rows of a table, decoded one at a time from its columns.
+///
+/// Each row has one value per column, in column order: an atom for most
+/// columns, or a `KView::Symbol` borrowed from the message for symbols.
+/// Only simple list columns can be streamed. See `LazyTable::rows`.
+pub struct Rows<'a> {
+    names: &'a [Symbol],
+    columns: Vec<Cursor<'a>>,
+    remaining: usize,
+}
+
+// the position reached in one column
+enum Cursor<'a> {
+    Fixed { atom: KType, data: &'a [u8], width: usize },
+    Symbols(SplitTerminator<'a, char>),
+}
+
+impl<'a> Rows<'a> {
+    pub(crate) fn new(names: &'a [Symbol], columns: Vec<KView<'a>>) -> Result<Rows<'a>, Error> {
+        let mut len = None;
+        let mut cursors = Vec::with_capacity(columns.len());
+        for (name, column) in names.iter().zip(columns) {
+            let column = match column {
+                KView::Vector(_, v) => v,
+                _ => return Err(Error::new(ErrorKind::Unsupported,
+                    format!("column `{}` is not a simple list, so can't be streamed", name))),
+            };
+            if *len.get_or_insert(column.len()) != column.len() {
+                return Err(invalid("table columns have different lengths"))
+            };
+            cursors.push(Cursor::new(column));
+        }
+        Ok(Rows { names, columns: cursors, remaining: len.unwrap_or(0) })
+    }
+
+    pub fn names(&self) -> &'a [Symbol] {
+        self.names
+    }
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = Vec<KView<'a>>;
+
+    fn next(&mut self) -> Option<Vec<KView<'a>>> {
+        if self.remaining == 0 {
+            return None
+        };
+        self.remaining -= 1;
+        Some(self.columns.iter_mut().map(Cursor::next).collect())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Rows<'_> {}
+
+impl<'a> Cursor<'a> {
+    fn new(column: VectorView<'a>) -> Cursor<'a> {
+        let (code, data): (i8, &'a [u8]) = match column {
+            VectorView::Symbol(s)    => return Cursor::Symbols(s.iter()),
+            VectorView::Boolean(c)   => (1, c.as_bytes()),
+            VectorView::Guid(c)      => (2, c.as_bytes()),
+            VectorView::Byte(b)      => (4, b),
+            VectorView::Short(c)     => (5, c.as_bytes()),
+            VectorView::Int(c)       => (6, c.as_bytes()),
+            VectorView::Long(c)      => (7, c.as_bytes()),
+            VectorView::Real(c)      => (8, c.as_bytes()),
+            VectorView::Float(c)     => (9, c.as_bytes()),
+            VectorView::Char(s)      => (10, s),
+            VectorView::Timestamp(c) => (12, c.as_bytes()),
+            VectorView::Month(c)     => (13, c.as_bytes()),
+            VectorView::Date(c)      => (14, c.as_bytes()),
+            VectorView::Datetime(c)  => (15, c.as_bytes()),
+            VectorView::Timespan(c)  => (16, c.as_bytes()),
+            VectorView::Minute(c)    => (17, c.as_bytes()),
+            VectorView::Second(c)    => (18, c.as_bytes()),
+            VectorView::Time(c)      => (19, c.as_bytes()),
+        };
+        let width = KVector::new(code).and_then(|v| v.width()).unwrap();
+        match KObj::new(-code) {
+            KObj::Atom(atom) => Cursor::Fixed { atom, data, width },
+            _ => unreachable!("every simple list has a matching atom"),
+        }
+    }
+
+    // callers make sure the column isn't exhausted
+    fn next(&mut self) -> KView<'a> {
+        match self {
+            Cursor::Fixed { atom, data, width } => {
+                let (element, rest) = data.split_at(*width);
+                *data = rest;
+                KView::Atom(atom.deserialize(element))
+            },
+            Cursor::Symbols(symbols) => KView::Symbol(symbols.next().unwrap()),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A q symbol. Clones share the same text, so a symbol from a
+/// `SymbolInterner` costs a reference count rather than an allocation.
+#[derive(Clone)]
+pub struct Symbol {
+    text: Arc<str>,
+    // the bytes it was read from, when they aren't UTF-8 and were kept
+    raw: Option<Arc<[u8]>>,
+}
+
+impl Symbol {
+    pub fn new(s: &str) -> Symbol {
+        Symbol { text: Arc::from(s), raw: None }
+    }
+
+    /// A symbol that is sent as exactly `bytes`. If they aren't UTF-8 its
+    /// text is a lossy copy of them.
+    pub fn from_bytes(bytes: &[u8]) -> Symbol {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Symbol::new(s),
+            Err(_) => Symbol {
+                text: Arc::from(String::from_utf8_lossy(bytes).as_ref()),
+                raw: Some(Arc::from(bytes)),
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// The bytes sent for the symbol.
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.raw {
+            Some(raw) => raw,
+            None => self.text.as_bytes(),
+        }
+    }
+
+    /// False if the symbol holds bytes that aren't UTF-8.
+    pub fn is_utf8(&self) -> bool {
+        self.raw.is_none()
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        // interned symbols share their text, which is quicker to check
+        (Arc::ptr_eq(&self.text, &other.text) && self.raw == other.raw) || self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // must match [u8], since interners look symbols up by their bytes
+        self.as_bytes().hash(state)
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Borrow<[u8]> for Symbol {
+    fn borrow(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Symbol { Symbol::new(s) }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Symbol { Symbol { text: Arc::from(s), raw: None } }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.text.fmt(f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.text.fmt(f)
+    }
+}
+
+/// Hands out one shared `Symbol` per distinct name, so a feed that repeats
+/// the same symbols only allocates each of them once.
+///
+/// Symbols are never evicted. Call `clear` if the set of names a long-lived
+/// connection sees keeps growing.
+#[derive(Debug, Default)]
+pub struct SymbolInterner {
+    symbols: HashSet<Symbol>,
+}
+
+impl SymbolInterner {
+    pub fn new() -> SymbolInterner {
+        SymbolInterner::default()
+    }
+
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        self.intern_bytes(s.as_bytes())
+    }
+
+    /// Like `intern`, but keeps bytes that aren't UTF-8, see `Symbol::from_bytes`.
+    pub fn intern_bytes(&mut self, bytes: &[u8]) -> Symbol {
+        if let Some(sym) = self.symbols.get(bytes) {
+            return sym.clone()
+        };
+        let sym = Symbol::from_bytes(bytes);
+        self.symbols.insert(sym.clone());
+        sym
+    }
+
+    /// The number of distinct symbols seen.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interned_symbols_share_text() {
+        let mut interner = SymbolInterner::new();
+        let a = interner.intern("trade");
+        let b = interner.intern("trade");
+        assert!(Arc::ptr_eq(&a.text, &b.text));
+        assert_eq!(interner.intern("quote"), "quote");
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn compares_by_text() {
+        assert_eq!(Symbol::new("a"), Symbol::from("a".to_string()));
+        assert_ne!(Symbol::new("a"), Symbol::new("b"));
+    }
+
+    #[test]
+    fn keeps_bytes_that_are_not_utf8() {
+        let sym = Symbol::from_bytes(b"caf\xe9");
+        assert!(!sym.is_utf8());
+        assert_eq!(sym.as_bytes(), b"caf\xe9");
+        assert_eq!(sym.as_str(), "caf\u{fffd}");
+        assert_ne!(sym, Symbol::new("caf\u{fffd}"));
+        assert!(Symbol::from_bytes(b"cafe").is_utf8());
+    }
+}
@@ -0,0 +1,369 @@
+//! kdb+ temporal values, held exactly as they are on the wire: a count of
+//! the type's unit from the kdb+ epoch of 2000.01.01, or from midnight for
+//! the time types. Conversions to and from `chrono` are provided, but the
+//! raw value is what gets sent, so every type round-trips bit for bit.
+//!
+//! Each type has `NULL`, `INF` and `NEG_INF` constants for kdb+'s in-band
+//! nulls and infinities, which convert to `None` rather than to a `chrono`
+//! value.
+use std::convert::TryFrom;
+use std::fmt;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+const NANOS_PER_DAY: i64 = 86_400 * NANOS_PER_SEC;
+
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0)
+}
+
+/// Nanoseconds since 2000.01.01D00:00.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub i64);
+
+/// Months since 2000.01.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Month(pub i32);
+
+/// Days since 2000.01.01.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date(pub i32);
+
+/// Fractional days since 2000.01.01. Deprecated in kdb+ in favour of
+/// `Timestamp`, but still found in older HDBs.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Datetime(pub f64);
+
+/// A duration in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timespan(pub i64);
+
+/// Minutes, usually since midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Minute(pub i32);
+
+/// Seconds, usually since midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Second(pub i32);
+
+/// Milliseconds, usually since midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Time(pub i32);
+
+// the null and infinities of a type held as an integer
+macro_rules! specials {
+    ($($t:ident($int:ty)),*) => {$(
+        impl $t {
+            pub const NULL: $t = $t(<$int>::MIN);
+            pub const INF: $t = $t(<$int>::MAX);
+            pub const NEG_INF: $t = $t(-<$int>::MAX);
+
+            pub fn is_null(self) -> bool {
+                self == $t::NULL
+            }
+
+            pub fn is_infinite(self) -> bool {
+                self == $t::INF || self == $t::NEG_INF
+            }
+
+            // the value, unless it's a null or infinity
+            fn finite(self) -> Option<$int> {
+                if self.is_null() || self.is_infinite() { None } else { Some(self.0) }
+            }
+        }
+    )*}
+}
+
+specials!(Timestamp(i64), Month(i32), Date(i32), Timespan(i64), Minute(i32), Second(i32), Time(i32));
+
+impl Datetime {
+    pub const NULL: Datetime = Datetime(f64::NAN);
+    pub const INF: Datetime = Datetime(f64::INFINITY);
+    pub const NEG_INF: Datetime = Datetime(f64::NEG_INFINITY);
+
+    pub fn is_null(self) -> bool {
+        self.0.is_nan()
+    }
+
+    pub fn is_infinite(self) -> bool {
+        self.0.is_infinite()
+    }
+}
+
+impl Timestamp {
+    pub fn to_datetime(self) -> Option<DateTime<Utc>> {
+        let nanos = Duration::nanoseconds(self.finite()?);
+        Some(DateTime::from_utc(epoch().checked_add_signed(nanos)?, Utc))
+    }
+
+    /// None if `dt` is outside the range a timestamp can hold, roughly the
+    /// years 1708 to 2292.
+    pub fn from_datetime(dt: &DateTime<Utc>) -> Option<Timestamp> {
+        let since = dt.naive_utc().signed_duration_since(epoch());
+        since.num_nanoseconds().map(Timestamp)
+    }
+}
+
+impl Month {
+    /// The first day of the month.
+    pub fn to_date(self) -> Option<NaiveDate> {
+        let m = self.finite()?;
+        NaiveDate::from_ymd_opt(2000 + m.div_euclid(12), m.rem_euclid(12) as u32 + 1, 1)
+    }
+
+    /// The month `date` falls in.
+    pub fn from_date(date: &NaiveDate) -> Month {
+        Month((date.year() - 2000) * 12 + date.month0() as i32)
+    }
+}
+
+impl Date {
+    pub fn to_date(self) -> Option<NaiveDate> {
+        epoch().date().checked_add_signed(Duration::days(self.finite()? as i64))
+    }
+
+    pub fn from_date(date: &NaiveDate) -> Option<Date> {
+        let days = date.signed_duration_since(epoch().date()).num_days();
+        i32::try_from(days).ok().map(Date)
+    }
+}
+
+impl Datetime {
+    /// Rounded to the nearest millisecond, the precision kdb+ shows.
+    pub fn to_datetime(self) -> Option<DateTime<Utc>> {
+        if !self.0.is_finite() {
+            return None
+        };
+        let millis = (self.0 * 86_400_000.0).round();
+        if millis.abs() > i64::MAX as f64 / 2.0 {
+            return None
+        };
+        Some(DateTime::from_utc(epoch().checked_add_signed(Duration::milliseconds(millis as i64))?, Utc))
+    }
+
+    pub fn from_datetime(dt: &DateTime<Utc>) -> Datetime {
+        let since = dt.naive_utc().signed_duration_since(epoch());
+        let nanos = since.num_nanoseconds()
+            .map(|n| n as f64)
+            .unwrap_or_else(|| since.num_milliseconds() as f64 * 1e6);
+        Datetime(nanos / NANOS_PER_DAY as f64)
+    }
+}
+
+impl Timespan {
+    pub fn to_duration(self) -> Option<Duration> {
+        Some(Duration::nanoseconds(self.finite()?))
+    }
+
+    pub fn from_duration(d: &Duration) -> Option<Timespan> {
+        d.num_nanoseconds().map(Timespan)
+    }
+}
+
+impl Minute {
+    pub fn to_duration(self) -> Option<Duration> {
+        Some(Duration::minutes(self.finite()? as i64))
+    }
+
+    /// None unless the value is within a day.
+    pub fn to_time(self) -> Option<NaiveTime> {
+        time_of_day(self.finite()? as i64 * 60, 0)
+    }
+
+    pub fn from_time(t: &NaiveTime) -> Minute {
+        Minute((t.num_seconds_from_midnight() / 60) as i32)
+    }
+}
+
+impl Second {
+    pub fn to_duration(self) -> Option<Duration> {
+        Some(Duration::seconds(self.finite()? as i64))
+    }
+
+    /// None unless the value is within a day.
+    pub fn to_time(self) -> Option<NaiveTime> {
+        time_of_day(self.finite()? as i64, 0)
+    }
+
+    pub fn from_time(t: &NaiveTime) -> Second {
+        Second(t.num_seconds_from_midnight() as i32)
+    }
+}
+
+impl Time {
+    pub fn to_duration(self) -> Option<Duration> {
+        Some(Duration::milliseconds(self.finite()? as i64))
+    }
+
+    /// None unless the value is within a day.
+    pub fn to_time(self) -> Option<NaiveTime> {
+        let ms = self.finite()? as i64;
+        time_of_day(ms.div_euclid(1000), ms.rem_euclid(1000) as u32 * 1_000_000)
+    }
+
+    pub fn from_time(t: &NaiveTime) -> Time {
+        Time((t.num_seconds_from_midnight() * 1000 + t.nanosecond() / 1_000_000) as i32)
+    }
+}
+
+fn time_of_day(secs: i64, nanos: u32) -> Option<NaiveTime> {
+    if !(0..86_400).contains(&secs) {
+        return None
+    };
+    NaiveTime::from_num_seconds_from_midnight_opt(secs as u32, nanos)
+}
+
+// q shows durations with a sign, and lets hours run past 24
+fn sign(negative: bool) -> &'static str {
+    if negative { "-" } else { "" }
+}
+
+// q's spelling of a null or infinity, e.g. 0Nd or -0Wd. Finite values
+// outside chrono's range are shown as a cast from the raw value instead
+fn special(f: &mut fmt::Formatter, null: bool, neg_inf: bool, suffix: char) -> fmt::Result {
+    match (null, neg_inf) {
+        (true, _) => write!(f, "0N{}", suffix),
+        (false, true) => write!(f, "-0W{}", suffix),
+        (false, false) => write!(f, "0W{}", suffix),
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_datetime() {
+            Some(dt) => write!(f, "{}", dt.format("%Y.%m.%dD%H:%M:%S%.9f")),
+            None if self.finite().is_some() => write!(f, "`timestamp${}", self.0),
+            None => special(f, self.is_null(), *self == Timestamp::NEG_INF, 'p'),
+        }
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_date() {
+            Some(d) => write!(f, "{}m", d.format("%Y.%m")),
+            None if self.finite().is_some() => write!(f, "`month${}", self.0),
+            None => special(f, self.is_null(), *self == Month::NEG_INF, 'm'),
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_date() {
+            Some(d) => write!(f, "{}", d.format("%Y.%m.%d")),
+            None if self.finite().is_some() => write!(f, "`date${}", self.0),
+            None => special(f, self.is_null(), *self == Date::NEG_INF, 'd'),
+        }
+    }
+}
+
+impl fmt::Display for Datetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_datetime() {
+            Some(dt) => write!(f, "{}", dt.format("%Y.%m.%dT%H:%M:%S%.3f")),
+            None if !self.is_null() && !self.is_infinite() => write!(f, "`datetime${}", self.0),
+            None => special(f, self.is_null(), self.0 < 0.0, 'z'),
+        }
+    }
+}
+
+impl fmt::Display for Timespan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.finite().is_none() {
+            return special(f, self.is_null(), *self == Timespan::NEG_INF, 'n')
+        };
+        let n = self.0.unsigned_abs();
+        let (days, rest) = (n / NANOS_PER_DAY as u64, n % NANOS_PER_DAY as u64);
+        let secs = rest / NANOS_PER_SEC as u64;
+        write!(f, "{}{}D{:02}:{:02}:{:02}.{:09}", sign(self.0 < 0), days,
+            secs / 3600, secs / 60 % 60, secs % 60, rest % NANOS_PER_SEC as u64)
+    }
+}
+
+impl fmt::Display for Minute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.finite().is_none() {
+            return special(f, self.is_null(), *self == Minute::NEG_INF, 'u')
+        };
+        let m = self.0.unsigned_abs();
+        write!(f, "{}{:02}:{:02}", sign(self.0 < 0), m / 60, m % 60)
+    }
+}
+
+impl fmt::Display for Second {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.finite().is_none() {
+            return special(f, self.is_null(), *self == Second::NEG_INF, 'v')
+        };
+        let s = self.0.unsigned_abs();
+        write!(f, "{}{:02}:{:02}:{:02}", sign(self.0 < 0), s / 3600, s / 60 % 60, s % 60)
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.finite().is_none() {
+            return special(f, self.is_null(), *self == Time::NEG_INF, 't')
+        };
+        let ms = self.0.unsigned_abs();
+        let s = ms / 1000;
+        write!(f, "{}{:02}:{:02}:{:02}.{:03}", sign(self.0 < 0), s / 3600, s / 60 % 60, s % 60, ms % 1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_chrono() {
+        let dt = DateTime::<Utc>::from_utc(NaiveDate::from_ymd(2021, 3, 4).and_hms_nano(5, 6, 7, 8), Utc);
+        let ts = Timestamp::from_datetime(&dt).unwrap();
+        assert_eq!(ts.to_datetime(), Some(dt));
+        assert_eq!(Timestamp(0).to_datetime(), Some(DateTime::<Utc>::from_utc(epoch(), Utc)));
+        assert_eq!(Date(-1).to_date(), Some(NaiveDate::from_ymd(1999, 12, 31)));
+        assert_eq!(Date::from_date(&NaiveDate::from_ymd(2000, 1, 2)), Some(Date(1)));
+        assert_eq!(Month(-1).to_date(), Some(NaiveDate::from_ymd(1999, 12, 1)));
+        assert_eq!(Month::from_date(&NaiveDate::from_ymd(2001, 2, 15)), Month(13));
+        assert_eq!(Datetime(1.5).to_datetime(), Some(DateTime::<Utc>::from_utc(NaiveDate::from_ymd(2000, 1, 2).and_hms(12, 0, 0), Utc)));
+        assert_eq!(Time(3_723_004).to_time(), Some(NaiveTime::from_hms_milli(1, 2, 3, 4)));
+        assert_eq!(Minute(25 * 60).to_time(), None);
+    }
+
+    #[test]
+    fn displays_like_q() {
+        assert_eq!(Timestamp(1).to_string(), "2000.01.01D00:00:00.000000001");
+        assert_eq!(Month(13).to_string(), "2001.02m");
+        assert_eq!(Date(366).to_string(), "2001.01.01");
+        assert_eq!(Datetime(0.5).to_string(), "2000.01.01T12:00:00.000");
+        assert_eq!(Timespan(-(NANOS_PER_DAY + 1)).to_string(), "-1D00:00:00.000000001");
+        assert_eq!(Minute(1501).to_string(), "25:01");
+        assert_eq!(Second(3661).to_string(), "01:01:01");
+        assert_eq!(Time(45_296_789).to_string(), "12:34:56.789");
+    }
+
+    #[test]
+    fn nulls_and_infinities() {
+        assert_eq!(Date::NULL.to_date(), None);
+        assert_eq!(Timestamp::INF.to_datetime(), None);
+        assert_eq!(Datetime::NULL.to_datetime(), None);
+        assert_eq!(Timespan::NEG_INF.to_duration(), None);
+        assert!(Time::NULL.is_null() && !Time::NULL.is_infinite());
+        assert_eq!(Timestamp::NULL.to_string(), "0Np");
+        assert_eq!(Date::NEG_INF.to_string(), "-0Wd");
+        assert_eq!(Datetime::INF.to_string(), "0Wz");
+        assert_eq!(Month::NULL.to_string(), "0Nm");
+        assert_eq!(Timespan::INF.to_string(), "0Wn");
+        assert_eq!(Minute::NULL.to_string(), "0Nu");
+        assert_eq!(Second::INF.to_string(), "0Wv");
+        assert_eq!(Time::NEG_INF.to_string(), "-0Wt");
+    }
+
+    #[test]
+    fn displays_values_out_of_chrono_range() {
+        assert_eq!(Date(2_147_483_000).to_string(), "`date$2147483000");
+        assert_eq!(Month(-2_147_483_000).to_string(), "`month$-2147483000");
+        assert_eq!(Datetime(1e9).to_string(), "`datetime$1000000000");
+    }
+}
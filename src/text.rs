@@ -0,0 +1,53 @@
+use std::borrow::Cow;
+use std::io::Error;
+use super::decode::invalid;
+use super::symbol::{Symbol, SymbolInterner};
+
+/// How `Kdb::read` treats symbols and chars that aren't UTF-8, e.g. Latin-1
+/// text in a legacy table. Char vectors are always kept as their bytes.
+///
+/// Views, from `read_view` or `read_lazy`, borrow text straight from the
+/// message, so they always need it to be UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextMode {
+    /// Fail the message with `ErrorKind::InvalidData`. This includes char
+    /// atoms above 0x7F, which before text modes were read as the code point
+    /// of their byte, as `Bytes` still does.
+    #[default]
+    Strict,
+    /// Replace anything that isn't UTF-8 with U+FFFD.
+    Lossy,
+    /// Keep symbols as the bytes they were sent as, see `Symbol::from_bytes`,
+    /// and chars as the code point of their byte. Both go back out unchanged.
+    Bytes,
+}
+
+impl TextMode {
+    // the symbol for `bytes`, taken from `interner` if there is one
+    pub(crate) fn symbol(self, bytes: &[u8], interner: Option<&mut SymbolInterner>) -> Result<Symbol, Error> {
+        match (self, interner) {
+            (TextMode::Bytes, Some(interner)) => Ok(interner.intern_bytes(bytes)),
+            (TextMode::Bytes, None) => Ok(Symbol::from_bytes(bytes)),
+            (_, Some(interner)) => Ok(interner.intern(&self.text(bytes)?)),
+            (_, None) => Ok(Symbol::new(&self.text(bytes)?)),
+        }
+    }
+
+    pub(crate) fn char(self, byte: u8) -> Result<char, Error> {
+        match self {
+            _ if byte.is_ascii() => Ok(byte as char),
+            TextMode::Strict => Err(invalid(format!("char {} is not UTF-8", byte))),
+            TextMode::Lossy => Ok(char::REPLACEMENT_CHARACTER),
+            TextMode::Bytes => Ok(byte as char),
+        }
+    }
+
+    // text with no bytes to keep, like an error message, is read lossily in
+    // `Bytes` mode
+    pub(crate) fn text(self, bytes: &[u8]) -> Result<Cow<'_, str>, Error> {
+        match self {
+            TextMode::Strict => std::str::from_utf8(bytes).map(Cow::Borrowed).map_err(invalid),
+            TextMode::Lossy | TextMode::Bytes => Ok(String::from_utf8_lossy(bytes)),
+        }
+    }
+}
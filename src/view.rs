@@ -0,0 +1,318 @@
+use std::fmt;
+use std::io::Error;
+use std::marker::PhantomData;
+use std::str::SplitTerminator;
+use uuid::Uuid;
+use byteorder::{ByteOrder, LittleEndian};
+use crate::KObj;
+use super::adverb::Adverb;
+use super::attribute::Attribute;
+use super::decode::Decoder;
+use super::enumeration::Enumeration;
+use super::ktype::KType;
+use super::kvector::KVector;
+use super::symbol::{Symbol, SymbolInterner};
+
+/// A message decoded in place, borrowing symbols, strings and vector data
+/// from the buffer it was read into instead of copying them out.
+///
+/// Convert to an owned `KObj` with `into_kobj` (or `KObj::from`) when the
+/// value needs to outlive the buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KView<'a> {
+    Atom(KType),
+    Symbol(&'a str),
+    Vector(Attribute, VectorView<'a>),
+    Enum(Attribute, EnumView<'a>),
+    GenericList(Vec<KView<'a>>),
+    Dict(Box<KView<'a>>, Box<KView<'a>>),
+    Table(Symbols<'a>, Vec<KView<'a>>),
+    Lambda { context: &'a str, body: &'a str },
+    Error(&'a str),
+    Projection(Vec<KView<'a>>),
+    Composition(Vec<KView<'a>>),
+    Adverb(Adverb, Box<KView<'a>>),
+}
+
+/// A simple list borrowed from a message buffer, see `KVector`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VectorView<'a> {
+    Boolean(Column<'a, bool>),
+    Guid(Column<'a, Uuid>),
+    Byte(&'a [u8]),
+    Short(Column<'a, i16>),
+    Int(Column<'a, i32>),
+    Long(Column<'a, i64>),
+    Real(Column<'a, f32>),
+    Float(Column<'a, f64>),
+    Char(&'a [u8]),
+    Symbol(Symbols<'a>),
+    Timestamp(Column<'a, i64>),
+    Month(Column<'a, i32>),
+    Date(Column<'a, i32>),
+    Datetime(Column<'a, f64>),
+    Timespan(Column<'a, i64>),
+    Minute(Column<'a, i32>),
+    Second(Column<'a, i32>),
+    Time(Column<'a, i32>),
+}
+
+/// An enumeration borrowed from a message buffer, see `Enumeration`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnumView<'a> {
+    pub code: i8,
+    pub domain: &'a str,
+    pub indices: Column<'a, i64>,
+}
+
+/// A value that is stored on the wire as a fixed number of little endian bytes.
+pub trait FixedWidth: Sized + 'static {
+    const WIDTH: usize;
+    fn from_le(bytes: &[u8]) -> Self;
+}
+
+/// Fixed width vector data borrowed from a message buffer.
+///
+/// Message buffers have no alignment guarantees, so rather than a `&[T]`
+/// each element is read from its little endian bytes as it is accessed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Column<'a, T> {
+    data: &'a [u8],
+    element: PhantomData<T>,
+}
+
+/// The symbols of a symbol vector, borrowed from a message buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Symbols<'a> {
+    // every symbol, each followed by its null terminator
+    text: &'a str,
+    len: usize,
+}
+
+impl FixedWidth for bool {
+    const WIDTH: usize = 1;
+    fn from_le(bytes: &[u8]) -> bool { bytes[0] != 0 }
+}
+
+impl FixedWidth for Uuid {
+    const WIDTH: usize = 16;
+    fn from_le(bytes: &[u8]) -> Uuid { Uuid::from_slice(bytes).unwrap() }
+}
+
+impl FixedWidth for i16 {
+    const WIDTH: usize = 2;
+    fn from_le(bytes: &[u8]) -> i16 { LittleEndian::read_i16(bytes) }
+}
+
+impl FixedWidth for i32 {
+    const WIDTH: usize = 4;
+    fn from_le(bytes: &[u8]) -> i32 { LittleEndian::read_i32(bytes) }
+}
+
+impl FixedWidth for i64 {
+    const WIDTH: usize = 8;
+    fn from_le(bytes: &[u8]) -> i64 { LittleEndian::read_i64(bytes) }
+}
+
+impl FixedWidth for f32 {
+    const WIDTH: usize = 4;
+    fn from_le(bytes: &[u8]) -> f32 { LittleEndian::read_f32(bytes) }
+}
+
+impl FixedWidth for f64 {
+    const WIDTH: usize = 8;
+    fn from_le(bytes: &[u8]) -> f64 { LittleEndian::read_f64(bytes) }
+}
+
+impl<'a, T: FixedWidth> Column<'a, T> {
+    pub(crate) fn new(data: &'a [u8]) -> Column<'a, T> {
+        Column { data, element: PhantomData }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() / T::WIDTH
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        let start = index.checked_mul(T::WIDTH)?;
+        self.data.get(start..start.checked_add(T::WIDTH)?).map(T::from_le)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + 'a {
+        self.data.chunks_exact(T::WIDTH).map(T::from_le)
+    }
+
+    /// The raw little endian bytes of the column.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> Symbols<'a> {
+    pub(crate) fn new(text: &'a str, len: usize) -> Symbols<'a> {
+        Symbols { text, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> SplitTerminator<'a, char> {
+        self.text.split_terminator('\0')
+    }
+
+    /// The symbol at `index`. This walks the vector from the start, so use
+    /// `iter` to visit every symbol.
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        self.iter().nth(index)
+    }
+}
+
+impl VectorView<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            VectorView::Boolean(v)   => v.len(),
+            VectorView::Guid(v)      => v.len(),
+            VectorView::Byte(v)      => v.len(),
+            VectorView::Short(v)     => v.len(),
+            VectorView::Int(v)       => v.len(),
+            VectorView::Long(v)      => v.len(),
+            VectorView::Real(v)      => v.len(),
+            VectorView::Float(v)     => v.len(),
+            VectorView::Char(v)      => v.len(),
+            VectorView::Symbol(v)    => v.len(),
+            VectorView::Timestamp(v) => v.len(),
+            VectorView::Month(v)     => v.len(),
+            VectorView::Date(v)      => v.len(),
+            VectorView::Datetime(v)  => v.len(),
+            VectorView::Timespan(v)  => v.len(),
+            VectorView::Minute(v)    => v.len(),
+            VectorView::Second(v)    => v.len(),
+            VectorView::Time(v)      => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_kvector(&self) -> KVector {
+        self.to_kvector_with(&mut Symbol::new)
+    }
+
+    // `symbol` makes the owned copy of each symbol, e.g. from an interner
+    pub(crate) fn to_kvector_with<F: FnMut(&str) -> Symbol>(&self, symbol: &mut F) -> KVector {
+        match self {
+            VectorView::Boolean(v)   => KVector::Boolean(v.iter().collect()),
+            VectorView::Guid(v)      => KVector::Guid(v.iter().collect()),
+            VectorView::Byte(v)      => KVector::Byte(v.to_vec()),
+            VectorView::Short(v)     => KVector::Short(v.iter().collect()),
+            VectorView::Int(v)       => KVector::Int(v.iter().collect()),
+            VectorView::Long(v)      => KVector::Long(v.iter().collect()),
+            VectorView::Real(v)      => KVector::Real(v.iter().collect()),
+            VectorView::Float(v)     => KVector::Float(v.iter().collect()),
+            VectorView::Char(v)      => KVector::Char(v.to_vec()),
+            VectorView::Symbol(v)    => KVector::Symbol(v.iter().map(symbol).collect()),
+            VectorView::Timestamp(v) => KVector::Timestamp(v.iter().collect()),
+            VectorView::Month(v)     => KVector::Month(v.iter().collect()),
+            VectorView::Date(v)      => KVector::Date(v.iter().collect()),
+            VectorView::Datetime(v)  => KVector::Datetime(v.iter().collect()),
+            VectorView::Timespan(v)  => KVector::Timespan(v.iter().collect()),
+            VectorView::Minute(v)    => KVector::Minute(v.iter().collect()),
+            VectorView::Second(v)    => KVector::Second(v.iter().collect()),
+            VectorView::Time(v)      => KVector::Time(v.iter().collect()),
+        }
+    }
+}
+
+impl<'a> KView<'a> {
+    /// Decode a message body, i.e. everything after the 8 byte header.
+    pub fn decode(body: &'a [u8]) -> Result<KView<'a>, Error> {
+        Decoder::new(body).decode()
+    }
+
+    pub fn into_kobj(self) -> KObj {
+        self.into_kobj_with(&mut Symbol::new)
+    }
+
+    /// Like `into_kobj`, but takes every symbol from `interner`.
+    pub fn into_kobj_interned(self, interner: &mut SymbolInterner) -> KObj {
+        self.into_kobj_with(&mut |s: &str| interner.intern(s))
+    }
+
+    pub(crate) fn into_kobj_with<F: FnMut(&str) -> Symbol>(self, symbol: &mut F) -> KObj {
+        match self {
+            KView::Atom(k) => KObj::Atom(k),
+            KView::Symbol(s) => KObj::Atom(KType::Symbol(symbol(s))),
+            KView::Vector(a, v) => KObj::Vector(a, v.to_kvector_with(symbol)),
+            KView::Enum(a, e) => KObj::Enum(a, Enumeration { code: e.code, domain: symbol(e.domain), indices: e.indices.iter().collect() }),
+            KView::GenericList(l) => KObj::GenericList(l.into_iter().map(|k| k.into_kobj_with(symbol)).collect()),
+            KView::Dict(k, v) => KObj::Dict(Box::new(k.into_kobj_with(symbol)), Box::new(v.into_kobj_with(symbol))),
+            KView::Table(k, v) => KObj::Table(
+                k.iter().map(|s| KObj::Atom(KType::Symbol(symbol(s)))).collect(),
+                v.into_iter().map(|k| k.into_kobj_with(symbol)).collect()
+            ),
+            KView::Lambda { context, body } => KObj::Lambda { context: symbol(context), body: body.to_string() },
+            KView::Error(e) => KObj::Error(e.to_string()),
+            KView::Projection(p) => KObj::Projection(p.into_iter().map(|k| k.into_kobj_with(symbol)).collect()),
+            KView::Composition(c) => KObj::Composition(c.into_iter().map(|k| k.into_kobj_with(symbol)).collect()),
+            KView::Adverb(a, func) => KObj::Adverb(a, Box::new(func.into_kobj_with(symbol))),
+        }
+    }
+}
+
+impl<'a> From<KView<'a>> for KObj {
+    fn from(view: KView<'a>) -> KObj {
+        view.into_kobj()
+    }
+}
+
+impl fmt::Display for KView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.clone().into_kobj().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextMode;
+
+    // {x+y}[1]
+    const PROJECTION: [u8; 27] = [104, 2, 0, 0, 0, 100, 0, 10, 0, 5, 0, 0, 0, 123, 120, 43, 121, 125,
+        249, 1, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn read_projection() {
+        let data = KView::decode(&PROJECTION).unwrap().into_kobj();
+        assert_eq!(data, KObj::Projection(vec![KObj::lambda("{x+y}"), KObj::Atom(KType::Long(1))]));
+        assert_eq!(data.serialize(), &PROJECTION[1..]);
+        assert_eq!(format!("{}", data), "{x+y}[1j]");
+        let owned = Decoder::new(&PROJECTION).decode_owned(TextMode::Lossy, None).unwrap();
+        assert_eq!(owned, data);
+        let mut decoder = Decoder::new(&PROJECTION);
+        decoder.skip().unwrap();
+        assert_eq!(decoder.position(), PROJECTION.len());
+    }
+
+    #[test]
+    fn read_adverb() {
+        // +/
+        let data = KView::decode(&[107, 102, 1]).unwrap().into_kobj();
+        assert_eq!(data, KObj::Adverb(Adverb::Over, Box::new(KObj::Atom(KType::Operator(1)))));
+        assert_eq!(data.encoded_len(), 3);
+        assert_eq!(format!("{}", data), "+/");
+        // '[neg;count], i.e. '[-:;#:]
+        let composition = KObj::Composition(vec![KObj::Atom(KType::Unary(2)), KObj::Atom(KType::Unary(13))]);
+        let bytes = [&[105][..], &composition.serialize()].concat();
+        assert_eq!(KView::decode(&bytes).unwrap().into_kobj(), composition);
+    }
+}